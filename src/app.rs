@@ -1,8 +1,13 @@
 //! Application state management
 
+mod alarm;
 mod core;
+mod notify;
+mod now_playing;
 mod playlist;
 mod playlist_behavior;
+mod practice;
+mod sleep_timer;
 pub mod tray;
 pub mod ui;
 
@@ -15,11 +20,20 @@ use {
     crate::{
         config::Config,
         mpv_handler::{ActivePtyInput, MpvHandler},
+        remote::{PlayState, RemoteCmd, Status},
+        util::result_ext::ResultModalExt as _,
     },
     anyhow::Context as _,
+    chrono::{Local, NaiveDateTime},
     egui_sf2g::egui::{self, Context, Event, Key},
     playlist::Playlist,
-    std::{fmt::Display, path::PathBuf, sync::Mutex, time::Instant},
+    std::{
+        collections::VecDeque,
+        fmt::Display,
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::Instant,
+    },
     zbus::names::BusName,
 };
 
@@ -43,6 +57,13 @@ pub struct App {
     pub modal: ModalPopup,
     // On every update, try to find and play this song
     try_to_play: Option<PathBuf>,
+    /// Play the first song of the playlist once the scan is done
+    play_first_when_scanned: bool,
+    /// Alarms up to this moment have been handled
+    last_alarm_check: NaiveDateTime,
+    status_file: now_playing::StatusFile,
+    /// Connected while notifications are enabled
+    notifier: Option<notify::Notifier>,
 }
 
 #[derive(Default)]
@@ -94,6 +115,20 @@ impl App {
             user_stopped: true,
             song_change: false,
             played_path: None,
+            played_cue_track: None,
+            audio_devices: Vec::new(),
+            eq_preset: None,
+            applied_af: String::new(),
+            mixer: Vec::new(),
+            mix_pending: None,
+            waveform: None,
+            waveform_recv: None,
+            cover_art: None,
+            cover_art_recv: None,
+            practice: None,
+            sleep_timer: None,
+            fade_in: None,
+            queue: VecDeque::new(),
         };
         // Handle path argument for opening a folder (and optionally play a file)
         let mut play_this = None;
@@ -125,6 +160,10 @@ impl App {
             last_tooltip_update: Instant::now(),
             modal: ModalPopup::default(),
             try_to_play: None,
+            play_first_when_scanned: false,
+            last_alarm_check: Local::now().naive_local(),
+            status_file: now_playing::StatusFile::default(),
+            notifier: None,
         };
         if let Some(this) = play_this {
             app.queue_to_play(this.to_path_buf());
@@ -133,11 +172,7 @@ impl App {
     }
     // Update common to bg and fg
     pub fn common_update(&mut self) {
-        self.handle_mpv_events();
-        if self.core.mpv_handler.update(&mut self.modal).is_break() {
-            self.core.playlist_behavior = PlaylistBehavior::Stop;
-        }
-        self.core.handle_mpv_not_active(&mut self.modal);
+        self.core.update_mpv(&mut self.modal);
         if self.core.playlist.update() {
             self.ui.recalc_filt_entries(&self.core);
             self.refocus();
@@ -154,6 +189,31 @@ impl App {
                 self.try_to_play = None;
             }
         }
+        if self.play_first_when_scanned
+            && !self.core.playlist.is_scanning()
+            && self.core.playlist.len() > 0
+        {
+            self.play_first_when_scanned = false;
+            self.focus_and_play(0);
+        }
+        self.check_alarms();
+        self.update_status_file();
+        self.update_notifications();
+    }
+
+    fn check_alarms(&mut self) {
+        let now = Local::now().naive_local();
+        for idx in alarm::due_alarms(&self.core.cfg.alarms, self.last_alarm_check, now) {
+            let alarm = &mut self.core.cfg.alarms[idx];
+            if alarm.once {
+                alarm.enabled = false;
+            }
+            let (path, volume, fade_in) = (alarm.path.clone(), alarm.volume, alarm.fade_in);
+            logln!("Alarm went off, playing {}", path.display());
+            self.core.start_fade_in(volume, fade_in);
+            self.play_path(path);
+        }
+        self.last_alarm_check = now;
     }
 
     pub fn fg_update(&mut self, ui: &mut egui::Ui) {
@@ -165,12 +225,6 @@ impl App {
         self.ui.update(&mut self.core, ui, &mut self.modal);
     }
 
-    fn handle_mpv_events(&mut self) {
-        while let Some(event) = self.core.mpv_handler.poll_event() {
-            self.core.handle_event(event);
-        }
-    }
-
     /// Update when in the background (window not open)
     pub fn bg_update(&mut self) {
         self.common_update();
@@ -204,6 +258,14 @@ impl App {
                         " " if mpv_active => self.core.play_or_toggle_pause(&mut self.modal),
                         "<" if mpv_active => self.core.play_prev(&mut self.modal),
                         ">" if mpv_active => self.core.play_next(&mut self.modal),
+                        s if mpv_active && s == self.core.cfg.bookmark_prev_key => self
+                            .core
+                            .jump_bookmark(false)
+                            .err_popup("Bookmark jump error", &mut self.modal),
+                        s if mpv_active && s == self.core.cfg.bookmark_next_key => self
+                            .core
+                            .jump_bookmark(true)
+                            .err_popup("Bookmark jump error", &mut self.modal),
                         s => {
                             self.core.mpv_handler.send_input(s);
                         }
@@ -220,8 +282,9 @@ impl App {
                         Key::ArrowRight => self.core.mpv_handler.send_input("\x1b[C"),
                         Key::ArrowLeft => self.core.mpv_handler.send_input("\x1b[D"),
                         Key::Backspace => {
-                            self.core.cfg.speed = 1.0;
-                            self.core.mpv_handler.send_input("\x08");
+                            self.core
+                                .reset_speed_and_pitch()
+                                .err_popup("Speed reset error", &mut self.modal);
                         }
                         _ => (),
                     },
@@ -245,19 +308,24 @@ impl App {
     }
 
     pub(crate) fn update_tooltip(&mut self) {
+        // The icon only changes along with the state, so it can follow it right away
+        if let Some(trhandle) = &mut self.tray_handle {
+            let cover =
+                (self.core.cover_art.as_ref()).filter(|_| self.core.cfg.cover_art_tray_icon);
+            let state = self.core.cfg.tray_icon_state.then(|| self.status().state);
+            trhandle.set_icon(cover, state);
+        }
         // Don't spam DBus with updates every frame
         if self.last_tooltip_update.elapsed().as_secs() >= 1 {
             self.last_tooltip_update = Instant::now();
         } else {
             return;
         }
-        let mut buf = String::new();
-        if let Some(currently_playing) = self.currently_playing_name() {
-            buf.push_str(currently_playing);
+        let mut buf = self.render_template(&self.core.cfg.tooltip_template);
+        buf.truncate(buf.trim_end().len());
+        if let Some(timer) = &self.core.sleep_timer {
             buf.push('\n');
-        }
-        if let Some(last) = self.core.mpv_handler.mpv_output().lines().last() {
-            buf.push_str(last);
+            buf.push_str(&timer.label());
         }
         if let Some(trhandle) = &self.tray_handle {
             trhandle
@@ -292,7 +360,11 @@ impl App {
     }
     pub(crate) fn refocus(&mut self) {
         if let Some(path) = &self.core.played_path {
-            if let Some(pos) = self.core.playlist.pos_of_path(path) {
+            if let Some(pos) = self
+                .core
+                .playlist
+                .pos_of_item(path, self.core.played_cue_track)
+            {
                 self.focus(pos);
             }
         }
@@ -300,9 +372,116 @@ impl App {
     pub(crate) fn queue_to_play(&mut self, path: PathBuf) {
         self.try_to_play = Some(path);
     }
+    /// Run a command sent by another process. Returns the reply, if the command has one.
+    pub(crate) fn exec_remote(
+        &mut self,
+        cmd: RemoteCmd,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        let core = &mut self.core;
+        match cmd {
+            RemoteCmd::Play => {
+                if !core.mpv_handler.active() {
+                    core.play_selected_song(&mut self.modal);
+                } else if core.mpv_handler.paused() {
+                    core.mpv_handler
+                        .ipc(|b| b.set_pause(false))
+                        .unwrap_or(Ok(()))?;
+                }
+            }
+            RemoteCmd::Pause => core
+                .mpv_handler
+                .ipc(|b| b.set_pause(true))
+                .unwrap_or(Ok(()))?,
+            RemoteCmd::Toggle => core.play_or_toggle_pause(&mut self.modal),
+            RemoteCmd::Next => core.play_next(&mut self.modal),
+            RemoteCmd::Prev => core.play_prev(&mut self.modal),
+            RemoteCmd::Stop => core.stop_music()?,
+            RemoteCmd::Seek { target } => {
+                if let Some(info) = core.mpv_handler.time_info() {
+                    core.seek(target.resolve(info.pos))?;
+                }
+            }
+            RemoteCmd::Volume { volume } => {
+                core.cfg.volume = volume;
+                core.mpv_handler
+                    .ipc(|b| b.set_volume(volume))
+                    .unwrap_or(Ok(()))?;
+            }
+            RemoteCmd::Speed { speed } => match core.mpv_handler.ipc(|b| b.set_speed(speed)) {
+                Some(result) => result?,
+                None => core.set_stopped_speed(speed),
+            },
+            RemoteCmd::Enqueue { path } => {
+                if core.mpv_handler.active() {
+                    let rel_path = (core.cfg.music_folder.as_ref())
+                        .and_then(|folder| path.strip_prefix(folder).ok())
+                        .context("Only songs in the music folder can be queued")?;
+                    core.queue.push_back(rel_path.to_owned());
+                } else {
+                    self.play_path(path);
+                }
+            }
+            RemoteCmd::Open { path } => {
+                if path.is_dir() {
+                    open_folder(&mut self.core, path);
+                } else {
+                    self.play_path(path);
+                }
+            }
+            RemoteCmd::Status => return Ok(Some(serde_json::to_value(self.status())?)),
+        }
+        Ok(None)
+    }
+    pub(crate) fn status(&self) -> Status {
+        let core = &self.core;
+        let state = if !core.mpv_handler.active() {
+            PlayState::Stopped
+        } else if core.mpv_handler.paused() {
+            PlayState::Paused
+        } else {
+            PlayState::Playing
+        };
+        let path = (core.cfg.music_folder.as_ref())
+            .zip(core.played_path.as_ref())
+            .map(|(folder, path)| folder.join(path));
+        let (pos, duration, volume, speed) = match core.mpv_handler.observed() {
+            Some(o) => (o.time_pos, o.duration, o.volume, o.speed),
+            None => (0.0, 0.0, core.cfg.volume, core.stopped_speed()),
+        };
+        Status {
+            state,
+            path: path.filter(|_| state != PlayState::Stopped),
+            pos,
+            duration,
+            volume,
+            speed,
+        }
+    }
+    /// Play a song or playlist file, or a folder from the start.
+    ///
+    /// Songs in the music folder are played from there, otherwise their folder gets opened.
+    pub(crate) fn play_path(&mut self, path: PathBuf) {
+        let playlist = &self.core.playlist;
+        let in_music_folder = (self.core.cfg.music_folder.as_ref())
+            .and_then(|folder| path.strip_prefix(folder).ok())
+            .filter(|rel| playlist.is_scanning() || playlist.iter().any(|item| item.path == *rel))
+            .map(Path::to_path_buf);
+        if path.is_dir() {
+            open_folder(&mut self.core, path);
+            self.play_first_when_scanned = true;
+        } else if let Some(rel_path) = in_music_folder {
+            self.queue_to_play(rel_path);
+        } else if let Some(parent) = path.parent() {
+            open_folder(&mut self.core, parent.to_owned());
+            let stripped = path.strip_prefix(parent).unwrap();
+            self.queue_to_play(stripped.to_path_buf());
+        }
+    }
 }
 
 pub(crate) fn open_folder(core: &mut Core, path: PathBuf) {
     core.cfg.music_folder = Some(path);
+    // The queue is relative to the music folder
+    core.queue.clear();
     core.start_scan();
 }
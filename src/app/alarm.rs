@@ -0,0 +1,90 @@
+use {
+    crate::config::Alarm,
+    chrono::{Days, NaiveDateTime, TimeDelta},
+    std::time::Instant,
+};
+
+/// How late an alarm can still go off, for example after the computer woke up from suspend
+const MAX_LATENESS: TimeDelta = TimeDelta::minutes(1);
+
+/// Indices of the enabled alarms that went off after `from`, up to and including `to`
+pub fn due_alarms(alarms: &[Alarm], from: NaiveDateTime, to: NaiveDateTime) -> Vec<usize> {
+    let from = from.max(to - MAX_LATENESS);
+    let yesterday = to.date().checked_sub_days(Days::new(1));
+    (alarms.iter().enumerate())
+        .filter(|(_, alarm)| alarm.enabled)
+        .filter(|(_, alarm)| {
+            // Check yesterday too, in case the range crosses midnight
+            [yesterday, Some(to.date())]
+                .into_iter()
+                .flatten()
+                .map(|date| date.and_time(alarm.time))
+                .any(|when| from < when && when <= to)
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Raises the volume gradually once the music starts
+pub struct FadeIn {
+    /// Volume to end up at
+    pub to: u8,
+    pub secs: f64,
+    /// Whether the song to fade in was started.
+    /// Until then, another song might still be playing, which is left alone.
+    pub spawned: bool,
+    /// When mpv started playing
+    pub started: Option<Instant>,
+}
+
+impl FadeIn {
+    pub fn new(to: u8, secs: f64) -> Self {
+        Self {
+            to,
+            secs,
+            spawned: false,
+            started: None,
+        }
+    }
+    /// Volume to play at right now
+    pub fn current_volume(&self) -> u8 {
+        self.volume_at(
+            self.started
+                .map_or(0.0, |started| started.elapsed().as_secs_f64()),
+        )
+    }
+    /// Volume to play at `elapsed` seconds into the fade-in
+    pub fn volume_at(&self, elapsed: f64) -> u8 {
+        if elapsed >= self.secs {
+            return self.to;
+        }
+        (f64::from(self.to) * elapsed / self.secs).round() as u8
+    }
+}
+
+#[test]
+fn test_due_alarms() {
+    use chrono::{NaiveDate, NaiveTime};
+    let alarm = |h, m, enabled| Alarm {
+        enabled,
+        time: NaiveTime::from_hms_opt(h, m, 0).unwrap(),
+        ..Default::default()
+    };
+    let alarms = [alarm(7, 0, true), alarm(7, 0, false), alarm(0, 0, true)];
+    let at = |d, h, m, s| {
+        NaiveDate::from_ymd_opt(2026, 3, d)
+            .unwrap()
+            .and_hms_opt(h, m, s)
+            .unwrap()
+    };
+    assert_eq!(due_alarms(&alarms, at(1, 6, 59, 59), at(1, 7, 0, 0)), [0]);
+    assert!(due_alarms(&alarms, at(1, 7, 0, 0), at(1, 7, 0, 1)).is_empty());
+    assert_eq!(due_alarms(&alarms, at(1, 23, 59, 59), at(2, 0, 0, 0)), [2]);
+    // Woke up from suspend long after the alarm
+    assert!(due_alarms(&alarms, at(1, 6, 0, 0), at(1, 9, 0, 0)).is_empty());
+    let fade = FadeIn::new(80, 20.0);
+    assert_eq!(fade.volume_at(0.0), 0);
+    assert_eq!(fade.volume_at(5.0), 20);
+    assert_eq!(fade.volume_at(25.0), 80);
+    assert_eq!(FadeIn::new(80, 0.0).volume_at(0.0), 80);
+}
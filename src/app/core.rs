@@ -1,15 +1,32 @@
+#[cfg(test)]
+mod tests;
+
 use {
-    super::{ModalPopup, PlaylistBehavior, playlist::Playlist},
+    super::{
+        ModalPopup, PlaylistBehavior,
+        alarm::FadeIn,
+        playlist::Playlist,
+        practice::Practice,
+        sleep_timer::{self, SleepTimer, SleepTimerKind},
+    },
     crate::{
-        config::{Config, CustomDemuxerEntry, PredicateSliceExt},
-        ipc::Bridge,
+        audio_filter::{self, MixTrack, Normalization, ReplayGain},
+        config::{Bookmark, Config, CustomDemuxerEntry, PredicateSliceExt, SpeedMode},
+        cover_art::{self, CoverArt},
+        cue::CueTrack,
+        ipc::{AudioDeviceEntry, Bridge, IpcEvent, TrackKind},
         logln,
         mpv_handler::{CustomDemuxer, MpvHandler},
         util::result_ext::ResultModalExt,
+        waveform::{self, Waveform},
     },
+    anyhow::Context as _,
     std::{
+        collections::{BTreeMap, VecDeque},
         ffi::OsStr,
         path::{Path, PathBuf},
+        sync::mpsc::Receiver,
+        time::{Duration, Instant},
     },
 };
 
@@ -28,6 +45,32 @@ pub struct Core {
     /// We also keep track of which path we're playing, so we can recalculate
     /// its index in the playlist, if needed.
     pub(crate) played_path: Option<PathBuf>,
+    /// Number of the played track, if `played_path` is a cue sheet
+    pub(crate) played_cue_track: Option<u32>,
+    /// Audio output devices, as last reported by mpv
+    pub(crate) audio_devices: Vec<AudioDeviceEntry>,
+    /// Name of the equalizer preset in effect for the current song
+    pub(crate) eq_preset: Option<String>,
+    /// The filters mpvfrog added to mpv's audio filter chain, so they can be swapped out later
+    pub(super) applied_af: String,
+    /// Mixer settings of the audio tracks of the current song
+    pub(crate) mixer: Vec<MixTrack>,
+    /// Number of audio tracks before a file was added for mixing.
+    ///
+    /// Once mpv has loaded the new track, the tracks get mixed.
+    pub(super) mix_pending: Option<usize>,
+    /// Waveform of the current song, for the seek bar
+    pub(crate) waveform: Option<Waveform>,
+    pub(super) waveform_recv: Option<Receiver<anyhow::Result<Waveform>>>,
+    /// Cover art of the current song
+    pub(crate) cover_art: Option<CoverArt>,
+    pub(super) cover_art_recv: Option<Receiver<anyhow::Result<Option<CoverArt>>>>,
+    /// Set while practice mode is on
+    pub(crate) practice: Option<Practice>,
+    pub(crate) sleep_timer: Option<SleepTimer>,
+    pub(crate) fade_in: Option<FadeIn>,
+    /// Songs to play next, before going on with the playlist. Relative to the music folder.
+    pub(crate) queue: VecDeque<PathBuf>,
 }
 
 impl Core {
@@ -57,12 +100,15 @@ impl Core {
             .iter()
             .find(|en| en.predicates.find_predicate_match(&path))
             .cloned();
-        self.play_song_with_demuxer(&path, demuxer_en, modal);
+        let cue_track =
+            (self.playlist.get(self.selected_song)).and_then(|item| item.cue_track.clone());
+        self.play_song_with_demuxer(&path, demuxer_en, cue_track.as_ref(), modal);
     }
     pub(crate) fn play_song_with_demuxer(
         &mut self,
         path: &Path,
         demuxer_en: Option<CustomDemuxerEntry>,
+        cue_track: Option<&CueTrack>,
         modal: &mut ModalPopup,
     ) {
         self.save_mpv_values_to_cfg();
@@ -70,18 +116,51 @@ impl Core {
         if let Some(music_folder) = &self.cfg.music_folder {
             self.played_path = Some(path.strip_prefix(music_folder).unwrap().to_owned());
         }
+        self.played_cue_track = cue_track.map(|track| track.number);
+        self.mixer.clear();
+        self.mix_pending = None;
+        self.practice = None;
+        self.waveform = None;
+        // Demuxed songs and cue sheets aren't something ffmpeg can decode by itself
+        self.waveform_recv = (self.cfg.waveform && demuxer_en.is_none() && cue_track.is_none())
+            .then(|| waveform::load(path.to_owned()));
+        self.cover_art = None;
+        self.cover_art_recv = self.cfg.cover_art.then(|| cover_art::load(path.to_owned()));
+        let cue_args = cue_track.map(CueTrack::mpv_args).unwrap_or_default();
 
-        let vol_arg = format!("--volume={}", self.cfg.volume);
-        let speed_arg = format!("--speed={}", self.cfg.speed);
-        let mut mpv_args = vec![
-            path.as_ref(),
-            "--input-ipc-server=/tmp/mpvfrog.sock".as_ref(),
-            vol_arg.as_ref(),
-            speed_arg.as_ref(),
-        ];
+        // A fade-in starts silent, and carries on into the next song if that one is short
+        let volume = match &mut self.fade_in {
+            Some(fade) => {
+                fade.spawned = true;
+                fade.current_volume()
+            }
+            None => self.cfg.volume,
+        };
+        let vol_arg = format!("--volume={volume}");
+        let speed_arg = format!("--speed={}", self.speed_for_played_song());
+        self.eq_preset = self.eq_preset_for(path, demuxer_en.as_ref());
+        self.applied_af = self.af_chain();
+        let af_arg = (!self.applied_af.is_empty()).then(|| format!("--af-add={}", self.applied_af));
+        let audio_device_arg =
+            (self.cfg.audio_device.as_ref()).map(|dev| format!("--audio-device={dev}"));
+        let replaygain_args = self.replaygain_args();
+        let mut mpv_args = vec![path.as_ref(), vol_arg.as_ref(), speed_arg.as_ref()];
         if !self.cfg.video {
             mpv_args.push("--no-video".as_ref());
         }
+        if !self.cfg.speed_mode.pitch_correction() {
+            mpv_args.push("--audio-pitch-correction=no".as_ref());
+        }
+        if let Some(arg) = &audio_device_arg {
+            mpv_args.push(arg.as_ref());
+        }
+        mpv_args.extend(replaygain_args.iter().map(<_ as AsRef<OsStr>>::as_ref));
+        mpv_args.extend(cue_args.iter().map(<_ as AsRef<OsStr>>::as_ref));
+        let profile_args = (self.cfg.ext_arg_profiles.iter())
+            .filter(|prof| prof.matches(path))
+            .flat_map(|prof| &prof.args);
+        let extra_args = self.cfg.extra_mpv_args.iter().chain(profile_args);
+        mpv_args.extend(extra_args.map(<_ as AsRef<OsStr>>::as_ref));
         let demuxer = match &demuxer_en {
             Some(en) => {
                 mpv_args.remove(0);
@@ -93,11 +172,20 @@ impl Core {
             }
             None => None,
         };
+        // Added after the user's arguments, so an `--af` among them doesn't replace it
+        if let Some(arg) = &af_arg {
+            mpv_args.push(arg.as_ref());
+        }
         crate::app::LOG.lock().unwrap().clear();
         logln!("Mpv args: {mpv_args:?}");
-        if let Err(e) = self.mpv_handler.play_music("mpv", mpv_args, demuxer) {
+        self.mpv_handler.set_backend(self.cfg.backend);
+        if let Err(e) = self
+            .mpv_handler
+            .play_music(&self.cfg.mpv_path, mpv_args, demuxer)
+        {
             modal.error("Play error", format!("{e:?}"));
             self.playlist_behavior = PlaylistBehavior::Stop;
+            self.fade_in = None;
         }
     }
     pub fn play_prev(&mut self, modal: &mut ModalPopup) {
@@ -119,19 +207,112 @@ impl Core {
         self.song_change = true;
     }
 
-    pub fn stop_music(&mut self) {
+    pub fn stop_music(&mut self) -> anyhow::Result<()> {
         self.save_mpv_values_to_cfg();
-        self.mpv_handler.stop_music();
+        self.fade_in.take_if(|fade| fade.spawned);
         self.user_stopped = true;
+        self.mpv_handler.stop_music()
     }
 
     pub(super) fn save_mpv_values_to_cfg(&mut self) {
         self.mpv_handler.ipc(|b| {
-            self.cfg.volume = b.observed.volume;
-            self.cfg.speed = b.observed.speed;
+            // The volume during a fade-in is only on the way to the real one
+            self.cfg.volume = (self.fade_in.as_ref())
+                .filter(|fade| fade.spawned)
+                .map_or(b.observed.volume, |fade| fade.to);
+            // The speed practice mode ramps up to isn't the song's own
+            let speed = (self.practice.as_ref())
+                .map_or(b.observed.speed, |practice| practice.original_speed);
+            if !self.cfg.remember_song_speed {
+                self.cfg.speed = speed;
+            } else if let Some(path) = &self.played_path {
+                let key = song_speed_key(path, self.played_cue_track);
+                remember_song_speed(&mut self.cfg.song_speeds, key, speed);
+            }
         });
     }
 
+    /// The speed to start the played song at
+    fn speed_for_played_song(&self) -> f64 {
+        if !self.cfg.remember_song_speed {
+            return self.cfg.speed;
+        }
+        (self.played_path.as_ref())
+            .and_then(|path| {
+                let key = song_speed_key(path, self.played_cue_track);
+                self.cfg.song_speeds.get(&key)
+            })
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Key of the selected song in the remembered song speeds
+    fn selected_speed_key(&self) -> Option<PathBuf> {
+        let item = self.playlist.get(self.selected_song)?;
+        let cue_track = item.cue_track.as_ref().map(|track| track.number);
+        Some(song_speed_key(&item.path, cue_track))
+    }
+
+    /// The speed the next song starts at, while nothing is playing.
+    ///
+    /// With per-song speeds, that's the speed of the selected song.
+    pub(crate) fn stopped_speed(&self) -> f64 {
+        if !self.cfg.remember_song_speed {
+            return self.cfg.speed;
+        }
+        (self.selected_speed_key())
+            .and_then(|key| self.cfg.song_speeds.get(&key))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Change the speed the next song starts at, while nothing is playing
+    pub(crate) fn set_stopped_speed(&mut self, speed: f64) {
+        if !self.cfg.remember_song_speed {
+            self.cfg.speed = speed;
+        } else if let Some(key) = self.selected_speed_key() {
+            remember_song_speed(&mut self.cfg.song_speeds, key, speed);
+        }
+    }
+
+    pub(crate) fn set_speed_mode(&mut self, mode: SpeedMode) -> anyhow::Result<()> {
+        self.cfg.speed_mode = mode;
+        let result = self
+            .mpv_handler
+            .ipc(|b| b.set_pitch_correction(mode.pitch_correction()));
+        result.unwrap_or(Ok(()))
+    }
+
+    pub(crate) fn set_level_meter(&mut self, enabled: bool) -> anyhow::Result<()> {
+        self.cfg.level_meter = enabled;
+        self.mpv_handler.ipc(|b| b.observed.levels.clear());
+        self.apply_audio_filters()
+    }
+
+    pub(crate) fn set_pitch(&mut self, semitones: f64) -> anyhow::Result<()> {
+        self.cfg.pitch_semitones = semitones;
+        self.apply_audio_filters()
+    }
+
+    /// Go back to normal speed and pitch
+    pub(crate) fn reset_speed_and_pitch(&mut self) -> anyhow::Result<()> {
+        match self.mpv_handler.ipc(|b| b.set_speed(1.0)) {
+            Some(result) => {
+                result?;
+                if self.cfg.remember_song_speed {
+                    if let Some(path) = &self.played_path {
+                        let key = song_speed_key(path, self.played_cue_track);
+                        self.cfg.song_speeds.remove(&key);
+                    }
+                } else {
+                    self.cfg.speed = 1.0;
+                }
+            }
+            None => self.set_stopped_speed(1.0),
+        }
+        self.set_pitch(0.0)
+    }
+
     /// Plays the selected song, or toggles the pause state if already playing
     pub fn play_or_toggle_pause(&mut self, modal: &mut ModalPopup) {
         if self.mpv_handler.active() {
@@ -143,27 +324,84 @@ impl Core {
         }
     }
 
-    pub(super) fn handle_mpv_not_active(&mut self, modal: &mut ModalPopup) {
+    /// Handle what mpv has been up to, and move on in the playlist if it has stopped playing
+    pub(super) fn update_mpv(&mut self, modal: &mut ModalPopup) {
+        while let Some(event) = self.mpv_handler.poll_event() {
+            self.handle_event(event);
+        }
+        if let Some(recv) = &self.waveform_recv
+            && let Ok(result) = recv.try_recv()
+        {
+            self.waveform_recv = None;
+            match result {
+                Ok(waveform) => self.waveform = Some(waveform),
+                Err(e) => logln!("Failed to load waveform: {e:#}"),
+            }
+        }
+        if let Some(recv) = &self.cover_art_recv
+            && let Ok(result) = recv.try_recv()
+        {
+            self.cover_art_recv = None;
+            match result {
+                Ok(art) => self.cover_art = art,
+                Err(e) => logln!("Failed to load cover art: {e:#}"),
+            }
+        }
+        if let Some(count) = self.mix_pending
+            && self.audio_track_ids().len() > count
+        {
+            self.mix_pending = None;
+            self.apply_mixer().err_popup("Mix error", modal);
+        }
+        if self.mpv_handler.update(modal).is_break() {
+            self.playlist_behavior = PlaylistBehavior::Stop;
+        }
+        self.update_practice()
+            .err_popup("Practice mode error", modal);
+        self.update_sleep_timer(modal);
+        self.update_fade_in(modal);
+        self.handle_mpv_not_active(modal);
+    }
+
+    fn handle_mpv_not_active(&mut self, modal: &mut ModalPopup) {
         if self.user_stopped {
             return;
         }
         if !self.mpv_handler.active() {
-            match self.playlist_behavior {
-                PlaylistBehavior::Stop => return,
-                PlaylistBehavior::Continue => {
-                    if self.selected_song + 1 < self.playlist.len() {
-                        self.selected_song += 1;
-                    } else {
+            // The song is over, whether or not anything plays next
+            if self
+                .sleep_timer
+                .as_mut()
+                .is_some_and(SleepTimer::song_finished)
+            {
+                self.finish_sleep_timer();
+                self.user_stopped = true;
+                return;
+            }
+            match self.next_queued() {
+                Some(idx) => self.selected_song = idx,
+                // Nothing plays next, so don't count the song as finished again next time
+                None => match self.playlist_behavior {
+                    PlaylistBehavior::Stop => {
+                        self.user_stopped = true;
                         return;
                     }
-                }
-                PlaylistBehavior::RepeatOne => {}
-                PlaylistBehavior::RepeatPlaylist => {
-                    self.selected_song += 1;
-                    if self.selected_song >= self.playlist.len() {
-                        self.selected_song = 0;
+                    PlaylistBehavior::Continue => {
+                        if self.selected_song + 1 < self.playlist.len() {
+                            self.selected_song += 1;
+                        } else {
+                            self.user_stopped = true;
+                            return;
+                        }
                     }
-                }
+                    PlaylistBehavior::RepeatOne => {}
+                    PlaylistBehavior::RepeatPlaylist => {
+                        self.selected_song += 1;
+                        if self.selected_song >= self.playlist.len() {
+                            self.selected_song = 0;
+                        }
+                    }
+                },
             }
             // If we reached this point, we can take this as the song having been changed
             self.song_change = true;
@@ -171,15 +409,423 @@ impl Core {
         }
     }
 
+    /// Playlist index of the next song in the queue, skipping ones that aren't in the playlist
+    fn next_queued(&mut self) -> Option<usize> {
+        while let Some(path) = self.queue.pop_front() {
+            match self.playlist.iter().position(|item| item.path == path) {
+                Some(idx) => return Some(idx),
+                None => logln!("Queued song is not in the playlist: {}", path.display()),
+            }
+        }
+        None
+    }
+
+    pub(crate) fn start_sleep_timer(&mut self, timer: SleepTimer) -> anyhow::Result<()> {
+        self.cancel_sleep_timer()?;
+        self.sleep_timer = Some(timer);
+        Ok(())
+    }
+    /// Turn the sleep timer off, undoing the fade-out if it has started
+    pub(crate) fn cancel_sleep_timer(&mut self) -> anyhow::Result<()> {
+        if let Some(vol) = self.sleep_timer.take().and_then(|timer| timer.fade_from) {
+            self.cfg.volume = vol;
+            self.mpv_handler
+                .ipc(|b| b.set_volume(vol))
+                .unwrap_or(Ok(()))?;
+        }
+        Ok(())
+    }
+    /// The sleep timer ran out and the music has been stopped.
+    /// Next time, play at the volume from before the fade-out.
+    fn finish_sleep_timer(&mut self) {
+        if let Some(vol) = self.sleep_timer.take().and_then(|timer| timer.fade_from) {
+            self.cfg.volume = vol;
+        }
+    }
+    fn update_sleep_timer(&mut self, modal: &mut ModalPopup) {
+        let Some(timer) = &mut self.sleep_timer else {
+            return;
+        };
+        let song_left = (self.mpv_handler.time_info())
+            .filter(|info| info.duration > 0.0)
+            .map(|info| info.duration - info.pos);
+        let Some(remaining) = timer.remaining_secs(song_left) else {
+            return;
+        };
+        if matches!(timer.kind, SleepTimerKind::At(_)) && remaining <= 0.0 {
+            if self.mpv_handler.active() {
+                self.stop_music().err_popup("Sleep timer error", modal);
+            }
+            self.user_stopped = true;
+            self.finish_sleep_timer();
+            return;
+        }
+        if self.cfg.sleep_timer.fade_out && remaining < sleep_timer::FADE_SECS {
+            self.mpv_handler
+                .ipc(|b| {
+                    let from = *timer.fade_from.get_or_insert(b.observed.volume);
+                    let vol = sleep_timer::fade_volume(from, remaining);
+                    if b.observed.volume == vol {
+                        return Ok(());
+                    }
+                    b.set_volume(vol)
+                })
+                .err_popup("Sleep timer error", modal);
+        }
+    }
+
+    /// Start the next song silent, and fade in to `volume` over `secs` seconds
+    pub(crate) fn start_fade_in(&mut self, volume: u8, secs: f64) {
+        self.fade_in = Some(FadeIn::new(volume, secs));
+    }
+    fn update_fade_in(&mut self, modal: &mut ModalPopup) {
+        let Some(fade) = self.fade_in.as_mut().filter(|fade| fade.spawned) else {
+            return;
+        };
+        // The fade-in starts once we can control mpv
+        let result = self.mpv_handler.ipc(|b| {
+            let started = *fade.started.get_or_insert_with(Instant::now);
+            let vol = fade.volume_at(started.elapsed().as_secs_f64());
+            if b.observed.volume != vol {
+                b.set_volume(vol)?;
+            }
+            anyhow::Ok(vol == fade.to)
+        });
+        result.err_popup("Fade-in error", modal);
+        if let Some(Ok(true)) = result {
+            self.cfg.volume = fade.to;
+            self.fade_in = None;
+        }
+    }
+
     pub(crate) fn seek(&mut self, pos: f64) -> anyhow::Result<()> {
         self.mpv_handler.ipc(|b| b.seek(pos)).unwrap_or(Ok(()))
     }
 
-    pub(crate) fn handle_event(&mut self, event: crate::ipc::IpcEvent) {
+    /// Raise or lower the volume by `by` percent
+    pub(crate) fn change_volume(&mut self, by: i32) -> anyhow::Result<()> {
+        let current = (self.mpv_handler.observed()).map_or(self.cfg.volume, |o| o.volume);
+        let volume = (i32::from(current) + by).clamp(0, 150) as u8;
+        self.cfg.volume = volume;
+        self.mpv_handler
+            .ipc(|b| b.set_volume(volume))
+            .unwrap_or(Ok(()))
+    }
+
+    /// Start practice mode at the start of the A-B loop
+    pub(crate) fn start_practice(&mut self) -> anyhow::Result<()> {
+        let original_speed =
+            (self.mpv_handler.ipc(|b| b.observed.speed)).unwrap_or_else(|| self.stopped_speed());
+        let mut practice = Practice::new(&self.cfg.practice, original_speed);
+        let count_in = self.cfg.practice.count_in;
+        let result = self.mpv_handler.ipc(|b| {
+            b.set_speed(practice.speed)?;
+            if let Some(a) = b.observed.ab_loop_a {
+                b.seek(a)?;
+            }
+            start_count_in(b, &mut practice, count_in)
+        });
+        self.practice = Some(practice);
+        result.unwrap_or(Ok(()))
+    }
+    /// Stop practice mode, going back to the speed from before it
+    pub(crate) fn stop_practice(&mut self) -> anyhow::Result<()> {
+        let Some(practice) = self.practice.take() else {
+            return Ok(());
+        };
+        let result = self.mpv_handler.ipc(|b| {
+            b.set_speed(practice.original_speed)?;
+            if practice.count_in_until.is_some() {
+                b.set_pause(false)?;
+            }
+            Ok(())
+        });
+        result.unwrap_or(Ok(()))
+    }
+    fn update_practice(&mut self) -> anyhow::Result<()> {
+        let Some(practice) = &mut self.practice else {
+            return Ok(());
+        };
+        let settings = &self.cfg.practice;
+        let result = self.mpv_handler.ipc(|b| {
+            if let Some(until) = practice.count_in_until
+                && Instant::now() >= until
+            {
+                practice.count_in_until = None;
+                b.set_pause(false)?;
+            }
+            let (Some(loop_a), Some(loop_b)) = (b.observed.ab_loop_a, b.observed.ab_loop_b) else {
+                return Ok(());
+            };
+            if practice.loop_wrapped(b.observed.time_pos, loop_a, loop_b) {
+                practice.next_iteration(settings);
+                b.set_speed(practice.speed)?;
+                start_count_in(b, practice, settings.count_in)?;
+            }
+            Ok(())
+        });
+        result.unwrap_or(Ok(()))
+    }
+
+    /// Bookmarks of the current song
+    pub(crate) fn bookmarks(&self) -> &[Bookmark] {
+        (self.played_path.as_ref())
+            .and_then(|path| self.cfg.bookmarks.get(path))
+            .map_or(&[], Vec::as_slice)
+    }
+    /// Bookmark the current song. Bookmarks are kept in order of their position.
+    pub(crate) fn add_bookmark(&mut self, bookmark: Bookmark) {
+        let Some(path) = &self.played_path else {
+            return;
+        };
+        let bookmarks = self.cfg.bookmarks.entry(path.clone()).or_default();
+        let idx = bookmarks.partition_point(|bm| bm.a <= bookmark.a);
+        bookmarks.insert(idx, bookmark);
+    }
+    pub(crate) fn remove_bookmark(&mut self, idx: usize) {
+        let Some(path) = &self.played_path else {
+            return;
+        };
+        if let Some(bookmarks) = self.cfg.bookmarks.get_mut(path) {
+            if idx < bookmarks.len() {
+                bookmarks.remove(idx);
+            }
+            if bookmarks.is_empty() {
+                self.cfg.bookmarks.remove(path);
+            }
+        }
+    }
+    /// Seek to a bookmark. For loop regions, the A-B loop is set too.
+    pub(crate) fn jump_to_bookmark(&mut self, idx: usize) -> anyhow::Result<()> {
+        let Some(bookmark) = self.bookmarks().get(idx).cloned() else {
+            return Ok(());
+        };
+        let result = self.mpv_handler.ipc(|b| {
+            if let Some(loop_b) = bookmark.b {
+                b.set_ab_loop(Some(bookmark.a), Some(loop_b))?;
+            }
+            b.seek(bookmark.a)
+        });
+        result.unwrap_or(Ok(()))
+    }
+    /// Jump to the next bookmark after the current position, or the one before it
+    pub(crate) fn jump_bookmark(&mut self, forward: bool) -> anyhow::Result<()> {
+        let Some(pos) = self.mpv_handler.ipc(|b| b.observed.time_pos) else {
+            return Ok(());
+        };
+        // Leave some slack, so going back from right after a bookmark skips it
+        const SLACK: f64 = 1.0;
+        let bookmarks = self.bookmarks();
+        let idx = if forward {
+            bookmarks.iter().position(|bm| bm.a > pos)
+        } else {
+            bookmarks.iter().rposition(|bm| bm.a < pos - SLACK)
+        };
+        match idx {
+            Some(idx) => self.jump_to_bookmark(idx),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn handle_event(&mut self, event: IpcEvent) {
         match event {
-            crate::ipc::IpcEvent::EndFile => {
+            IpcEvent::EndFile => {
                 self.save_mpv_values_to_cfg();
             }
+            IpcEvent::AudioDevices(devices) => self.audio_devices = devices,
+        }
+    }
+
+    /// Which equalizer preset to use for a song.
+    ///
+    /// A preset attached to the demuxer wins over one attached to a folder,
+    /// which wins over the globally selected one.
+    fn eq_preset_for(
+        &self,
+        path: &Path,
+        demuxer_en: Option<&CustomDemuxerEntry>,
+    ) -> Option<String> {
+        if let Some(name) = demuxer_en.and_then(|en| en.eq_preset.as_ref()) {
+            return Some(name.clone());
+        }
+        let rel_path =
+            (self.cfg.music_folder.as_ref()).and_then(|folder| path.strip_prefix(folder).ok());
+        let folder_preset = rel_path.and_then(|rel_path| {
+            (self.cfg.folder_eq_presets.iter())
+                .filter(|en| rel_path.starts_with(&en.folder))
+                // The most specific folder wins
+                .max_by_key(|en| en.folder.components().count())
+        });
+        match folder_preset {
+            Some(en) => Some(en.preset.clone()),
+            None => self.cfg.eq_preset.clone(),
+        }
+    }
+
+    /// The audio filter chain for the current song
+    pub(crate) fn af_chain(&self) -> String {
+        let eq = (self.eq_preset.as_deref()).and_then(|name| self.cfg.eq_preset_by_name(name));
+        audio_filter::af_chain(
+            eq,
+            self.cfg.pitch_semitones,
+            self.cfg.normalization,
+            self.cfg.level_meter,
+        )
+    }
+
+    fn replaygain_args(&self) -> Vec<String> {
+        if self.cfg.replaygain == ReplayGain::Off {
+            return Vec::new();
+        }
+        vec![
+            format!("--replaygain={}", self.cfg.replaygain.mpv_value()),
+            format!("--replaygain-preamp={}", self.cfg.replaygain_preamp),
+            format!(
+                "--replaygain-clip={}",
+                if self.cfg.replaygain_clip_prevention {
+                    "yes"
+                } else {
+                    "no"
+                }
+            ),
+        ]
+    }
+
+    /// Update mpv's ReplayGain and normalization, after the settings have changed
+    pub(crate) fn apply_loudness_settings(&mut self) -> anyhow::Result<()> {
+        let cfg = &self.cfg;
+        let result = self.mpv_handler.ipc(|b| {
+            b.set_replaygain(
+                cfg.replaygain.mpv_value(),
+                cfg.replaygain_preamp,
+                cfg.replaygain_clip_prevention,
+            )
+        });
+        result.unwrap_or(Ok(()))?;
+        self.apply_audio_filters()
+    }
+
+    /// Short description of the active loudness mode, if any
+    pub(crate) fn loudness_label(&self) -> Option<String> {
+        let rg = match self.cfg.replaygain {
+            ReplayGain::Off => None,
+            ReplayGain::Track => Some("ReplayGain: track"),
+            ReplayGain::Album => Some("ReplayGain: album"),
+        };
+        let norm = match self.cfg.normalization {
+            Normalization::Off => None,
+            Normalization::Loudnorm => Some("loudnorm"),
+            Normalization::Dynaudnorm => Some("dynaudnorm"),
+        };
+        match (rg, norm) {
+            (None, None) => None,
+            (Some(rg), None) if self.cfg.replaygain_preamp != 0.0 => {
+                Some(format!("{rg} {:+} dB", self.cfg.replaygain_preamp))
+            }
+            (Some(label), None) | (None, Some(label)) => Some(label.to_owned()),
+            (Some(rg), Some(norm)) => Some(format!("{rg} + {norm}")),
+        }
+    }
+
+    /// Update mpv's audio filters, after the settings have changed
+    pub(crate) fn apply_audio_filters(&mut self) -> anyhow::Result<()> {
+        let chain = self.af_chain();
+        let old = &self.applied_af;
+        self.mpv_handler
+            .ipc(|b| b.replace_af(old, &chain))
+            .unwrap_or(Ok(()))?;
+        self.applied_af = chain;
+        Ok(())
+    }
+
+    /// Select the equalizer preset to use, and apply it to the current song
+    pub(crate) fn set_eq_preset(&mut self, name: Option<String>) -> anyhow::Result<()> {
+        self.cfg.eq_preset.clone_from(&name);
+        self.eq_preset = name;
+        self.apply_audio_filters()
+    }
+
+    /// Switch audio output device, and remember it for the next time mpv starts.
+    ///
+    /// `None` lets mpv pick.
+    pub(crate) fn set_audio_device(&mut self, device: Option<String>) -> anyhow::Result<()> {
+        let name = device.as_deref().unwrap_or("auto");
+        let result = self.mpv_handler.ipc(|b| b.set_audio_device(name));
+        self.cfg.audio_device = device;
+        result.unwrap_or(Ok(()))
+    }
+    /// Ids of the audio tracks mpv has loaded
+    fn audio_track_ids(&mut self) -> Vec<u64> {
+        let ids = self.mpv_handler.ipc(|b| {
+            (b.observed.tracks.iter())
+                .filter(|track| track.kind == TrackKind::Audio)
+                .map(|track| track.id)
+                .collect()
+        });
+        ids.unwrap_or_default()
+    }
+    /// Make the mixer list the loaded audio tracks, keeping the settings of known ones
+    pub(crate) fn sync_mixer(&mut self) {
+        let ids = self.audio_track_ids();
+        self.mixer.retain(|track| ids.contains(&track.id));
+        for id in ids {
+            if !self.mixer.iter().any(|track| track.id == id) {
+                self.mixer.push(MixTrack::new(id));
+            }
+        }
+    }
+    /// Mix the audio tracks according to the mixer settings
+    pub(crate) fn apply_mixer(&mut self) -> anyhow::Result<()> {
+        self.sync_mixer();
+        let graph = audio_filter::mix_graph(&self.mixer);
+        let result = self.mpv_handler.ipc(|b| {
+            b.set_lavfi_complex(graph)?;
+            // The tracks can go out of sync when the graph changes.
+            // Seeking to where we are brings them back in sync.
+            b.seek(b.observed.time_pos)
+        });
+        result.unwrap_or(Ok(()))
+    }
+    /// Load `path` as an extra audio track, and mix it in once mpv has loaded it
+    pub(crate) fn mix_with_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let count = self.audio_track_ids().len();
+        let path = path.to_str().context("Path is not valid UTF-8")?;
+        self.mpv_handler
+            .ipc(|b| b.add_audio(path))
+            .unwrap_or(Ok(()))?;
+        self.mix_pending = Some(count);
+        Ok(())
+    }
+}
+
+/// Pause for the count-in before a practice iteration, if there is one
+fn start_count_in(b: &mut Bridge, practice: &mut Practice, secs: f64) -> anyhow::Result<()> {
+    if secs > 0.0 {
+        practice.count_in_until = Some(Instant::now() + Duration::from_secs_f64(secs));
+        b.set_pause(true)?;
+    }
+    Ok(())
+}
+
+/// Songs at normal speed are forgotten, so the config doesn't grow with every song played
+fn remember_song_speed(song_speeds: &mut BTreeMap<PathBuf, f64>, key: PathBuf, speed: f64) {
+    if speed == 1.0 {
+        song_speeds.remove(&key);
+    } else {
+        song_speeds.insert(key, speed);
+    }
+}
+
+/// Key of a song in the remembered song speeds.
+///
+/// The tracks of a cue sheet are all in the same file, so they get the track number appended.
+fn song_speed_key(path: &Path, cue_track: Option<u32>) -> PathBuf {
+    match cue_track {
+        Some(number) => {
+            let mut key = path.as_os_str().to_owned();
+            key.push(format!("#{number}"));
+            key.into()
         }
+        None => path.to_owned(),
     }
 }
@@ -0,0 +1,732 @@
+use {
+    super::Core,
+    crate::{
+        app::{
+            ModalPopup, PlaylistBehavior,
+            playlist::Playlist,
+            sleep_timer::{SleepTimer, SleepTimerKind},
+        },
+        audio_filter::{EqPreset, Normalization, ReplayGain},
+        config::{
+            Bookmark, Config, CustomDemuxerEntry, ExtArgProfile, FolderEqPreset, HasExtsPredicate,
+            SpeedMode,
+        },
+        cue::CueTrack,
+        ipc::fake_mpv::wait_until,
+        mpv_handler::{
+            MpvHandler,
+            fake::{FakeBackend, Spawned},
+        },
+    },
+    serde_json::json,
+    std::{
+        collections::VecDeque,
+        path::{Path, PathBuf},
+    },
+};
+
+fn test_core(behavior: PlaylistBehavior) -> (Core, Spawned) {
+    let (backend, spawned) = FakeBackend::new();
+    let core = Core {
+        cfg: Config {
+            music_folder: Some("/music".into()),
+            waveform: false,
+            ..Default::default()
+        },
+        playlist: Playlist::from_paths(["a.mp3", "b.mp3", "c.mp3"]),
+        selected_song: 0,
+        mpv_handler: MpvHandler::with_backend(Box::new(backend)),
+        playlist_behavior: behavior,
+        user_stopped: true,
+        song_change: false,
+        played_path: None,
+        played_cue_track: None,
+        audio_devices: Vec::new(),
+        eq_preset: None,
+        applied_af: String::new(),
+        mixer: Vec::new(),
+        mix_pending: None,
+        waveform: None,
+        waveform_recv: None,
+        cover_art: None,
+        cover_art_recv: None,
+        practice: None,
+        sleep_timer: None,
+        fade_in: None,
+        queue: VecDeque::new(),
+    };
+    (core, spawned)
+}
+
+/// The songs mpv has been started with so far
+fn played(spawned: &Spawned) -> Vec<PathBuf> {
+    spawned
+        .borrow()
+        .iter()
+        .map(|spawned| PathBuf::from(&spawned.args[0]))
+        .collect()
+}
+
+/// Make the currently playing fake mpv reach the end of the song and exit
+fn finish_song(spawned: &Spawned) {
+    let spawned = spawned.borrow();
+    let mpv = &spawned.last().unwrap().mpv;
+    mpv.end_file();
+    mpv.exit();
+}
+
+/// Update until `count` songs have been started
+fn update_until_played(core: &mut Core, spawned: &Spawned, count: usize) {
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        spawned.borrow().len() == count
+    });
+}
+
+/// Update until mpv is done, and check that nothing else gets played
+fn update_until_stopped(core: &mut Core, spawned: &Spawned) {
+    let mut modal = ModalPopup::default();
+    let count = spawned.borrow().len();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        !core.mpv_handler.active()
+    });
+    core.update_mpv(&mut modal);
+    assert!(!core.mpv_handler.active());
+    assert_eq!(spawned.borrow().len(), count);
+}
+
+#[test]
+fn continue_plays_next_song() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Continue);
+    core.play_selected_song(&mut ModalPopup::default());
+    finish_song(&spawned);
+    update_until_played(&mut core, &spawned, 2);
+    finish_song(&spawned);
+    update_until_played(&mut core, &spawned, 3);
+    assert_eq!(
+        played(&spawned),
+        ["/music/a.mp3", "/music/b.mp3", "/music/c.mp3"].map(PathBuf::from)
+    );
+    assert_eq!(core.selected_song, 2);
+    assert_eq!(core.played_path, Some("c.mp3".into()));
+    // The last song doesn't wrap around
+    finish_song(&spawned);
+    update_until_stopped(&mut core, &spawned);
+}
+
+#[test]
+fn repeat_playlist_wraps_around() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::RepeatPlaylist);
+    core.selected_song = 2;
+    core.play_selected_song(&mut ModalPopup::default());
+    finish_song(&spawned);
+    update_until_played(&mut core, &spawned, 2);
+    assert_eq!(
+        played(&spawned),
+        ["/music/c.mp3", "/music/a.mp3"].map(PathBuf::from)
+    );
+    assert_eq!(core.selected_song, 0);
+}
+
+#[test]
+fn repeat_one_replays_song() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::RepeatOne);
+    core.selected_song = 1;
+    core.play_selected_song(&mut ModalPopup::default());
+    finish_song(&spawned);
+    update_until_played(&mut core, &spawned, 2);
+    assert_eq!(
+        played(&spawned),
+        ["/music/b.mp3", "/music/b.mp3"].map(PathBuf::from)
+    );
+}
+
+#[test]
+fn stop_behavior_stops() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.play_selected_song(&mut ModalPopup::default());
+    finish_song(&spawned);
+    update_until_stopped(&mut core, &spawned);
+}
+
+#[test]
+fn user_stop_doesnt_advance() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Continue);
+    core.play_selected_song(&mut ModalPopup::default());
+    core.stop_music().unwrap();
+    let mpv = spawned.borrow()[0].mpv.clone();
+    wait_until(|| mpv.received_command("quit"));
+    update_until_stopped(&mut core, &spawned);
+}
+
+#[test]
+fn end_file_saves_volume_and_speed() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Continue);
+    core.play_selected_song(&mut ModalPopup::default());
+    let mpv = spawned.borrow()[0].mpv.clone();
+    mpv.property_change("volume", json!(42));
+    mpv.property_change("speed", json!(1.25));
+    mpv.end_file();
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.cfg.speed == 1.25
+    });
+    assert_eq!(core.cfg.volume, 42);
+    // The next song starts with the saved values
+    mpv.exit();
+    update_until_played(&mut core, &spawned, 2);
+    let args = &spawned.borrow()[1].args;
+    assert!(args.iter().any(|arg| arg == "--volume=42"));
+    assert!(args.iter().any(|arg| arg == "--speed=1.25"));
+}
+
+#[test]
+fn extra_args_and_ext_profiles() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.cfg.extra_mpv_args = vec!["--replaygain=album".into()];
+    core.cfg.ext_arg_profiles = vec![
+        ExtArgProfile {
+            exts: HasExtsPredicate {
+                ext_list: "flac MP3".into(),
+                case_sensitive: false,
+            },
+            args: vec!["--af=loudnorm".into()],
+        },
+        ExtArgProfile {
+            exts: HasExtsPredicate {
+                ext_list: "ogg".into(),
+                case_sensitive: false,
+            },
+            args: vec!["--no-config".into()],
+        },
+    ];
+    core.play_selected_song(&mut ModalPopup::default());
+    let args = &spawned.borrow()[0].args;
+    assert_eq!(
+        args[args.len() - 2..],
+        ["--replaygain=album", "--af=loudnorm"]
+    );
+}
+
+#[test]
+fn audio_device() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.cfg.audio_device = Some("pulse/headphones".into());
+    core.play_selected_song(&mut ModalPopup::default());
+    let mpv = spawned.borrow()[0].mpv.clone();
+    assert!(
+        spawned.borrow()[0]
+            .args
+            .iter()
+            .any(|arg| arg == "--audio-device=pulse/headphones")
+    );
+    core.set_audio_device(None).unwrap();
+    assert_eq!(core.cfg.audio_device, None);
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "audio-device", "auto"]}))
+    });
+}
+
+#[test]
+fn cue_track() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    let track = CueTrack {
+        number: 2,
+        title: None,
+        performer: None,
+        chapter: 1,
+        last: false,
+    };
+    let path = PathBuf::from("/music/album.cue");
+    core.play_song_with_demuxer(&path, None, Some(&track), &mut ModalPopup::default());
+    let args = &spawned.borrow()[0].args;
+    assert!(args.iter().any(|arg| arg == "--start=#2"));
+    assert!(args.iter().any(|arg| arg == "--end=#3"));
+    assert_eq!(core.played_cue_track, Some(2));
+}
+
+#[test]
+fn eq_preset_precedence() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    let mut loud = EqPreset::new("Loud".into());
+    loud.bass = 6.0;
+    core.cfg.eq_presets = vec![loud, EqPreset::new("Flat".into())];
+    core.cfg.eq_preset = Some("Loud".into());
+    core.cfg.folder_eq_presets = vec![FolderEqPreset {
+        folder: "live".into(),
+        preset: "Flat".into(),
+    }];
+    core.play_selected_song(&mut ModalPopup::default());
+    assert_eq!(core.eq_preset.as_deref(), Some("Loud"));
+    assert!(
+        spawned.borrow()[0]
+            .args
+            .iter()
+            .any(|arg| arg == "--af-add=@mpvfrog-bass:lavfi-bass=g=6")
+    );
+    let live_song = PathBuf::from("/music/live/d.mp3");
+    assert_eq!(
+        core.eq_preset_for(&live_song, None).as_deref(),
+        Some("Flat")
+    );
+    let demuxer = CustomDemuxerEntry {
+        eq_preset: Some("Loud".into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        core.eq_preset_for(&live_song, Some(&demuxer)).as_deref(),
+        Some("Loud")
+    );
+    // Renaming keeps the references intact
+    core.cfg.rename_eq_preset("Flat", "Neutral").unwrap();
+    assert_eq!(
+        core.eq_preset_for(&live_song, None).as_deref(),
+        Some("Neutral")
+    );
+    assert!(core.cfg.rename_eq_preset("Neutral", " ").is_err());
+    assert!(core.cfg.rename_eq_preset("Neutral", "Loud").is_err());
+    assert_eq!(core.cfg.unused_eq_preset_name("Loud"), "Loud 2");
+    // Removing leaves no references behind
+    core.cfg.remove_eq_preset("Loud");
+    core.cfg.remove_eq_preset("Neutral");
+    assert!(core.cfg.eq_presets.is_empty());
+    assert_eq!(core.cfg.eq_preset, None);
+    assert!(core.cfg.folder_eq_presets.is_empty());
+}
+
+#[test]
+fn loudness_settings() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.cfg.replaygain = ReplayGain::Album;
+    core.cfg.replaygain_preamp = -3.0;
+    core.play_selected_song(&mut ModalPopup::default());
+    let args = spawned.borrow()[0].args.clone();
+    for arg in [
+        "--replaygain=album",
+        "--replaygain-preamp=-3",
+        "--replaygain-clip=yes",
+    ] {
+        assert!(args.iter().any(|a| a == arg), "{arg} missing from {args:?}");
+    }
+    assert_eq!(
+        core.loudness_label().as_deref(),
+        Some("ReplayGain: album -3 dB")
+    );
+    core.cfg.replaygain = ReplayGain::Off;
+    core.cfg.normalization = Normalization::Dynaudnorm;
+    core.apply_loudness_settings().unwrap();
+    let mpv = spawned.borrow()[0].mpv.clone();
+    wait_until(|| {
+        let received = mpv.received();
+        received.contains(&json!({"command": ["set_property", "replaygain", "no"]}))
+            && received
+                .contains(&json!({"command": ["af", "add", "@mpvfrog-norm:lavfi-dynaudnorm"]}))
+    });
+    assert_eq!(core.loudness_label().as_deref(), Some("dynaudnorm"));
+    // Only mpvfrog's own filters get removed again
+    core.cfg.normalization = Normalization::Off;
+    core.apply_loudness_settings().unwrap();
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["af", "remove", "@mpvfrog-norm:lavfi-dynaudnorm"]}))
+    });
+}
+
+#[test]
+fn speed_per_song_and_tape_mode() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Continue);
+    core.cfg.remember_song_speed = true;
+    core.cfg.speed_mode = SpeedMode::Tape;
+    core.cfg.pitch_semitones = -12.0;
+    core.play_selected_song(&mut ModalPopup::default());
+    let args = spawned.borrow()[0].args.clone();
+    for arg in [
+        "--speed=1",
+        "--audio-pitch-correction=no",
+        "--af-add=@mpvfrog-pitch:lavfi-rubberband=pitch=0.5",
+    ] {
+        assert!(args.iter().any(|a| a == arg), "{arg} missing from {args:?}");
+    }
+    let mpv = spawned.borrow()[0].mpv.clone();
+    mpv.property_change("speed", json!(0.75));
+    mpv.end_file();
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        !core.cfg.song_speeds.is_empty()
+    });
+    assert_eq!(core.cfg.song_speeds[&PathBuf::from("a.mp3")], 0.75);
+    // The global speed is left alone, so the next song starts at normal speed
+    assert_eq!(core.cfg.speed, 1.0);
+    mpv.exit();
+    update_until_played(&mut core, &spawned, 2);
+    assert!(
+        spawned.borrow()[1]
+            .args
+            .iter()
+            .any(|arg| arg == "--speed=1")
+    );
+    // Like real mpv, report the initial speed on observing it
+    let mpv = spawned.borrow()[1].mpv.clone();
+    mpv.property_change("speed", json!(1.0));
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.ipc(|b| b.observed.speed) == Some(1.0)
+    });
+    // Going back to the first song restores its speed
+    core.selected_song = 0;
+    core.play_selected_song(&mut modal);
+    update_until_played(&mut core, &spawned, 3);
+    assert!(
+        spawned.borrow()[2]
+            .args
+            .iter()
+            .any(|arg| arg == "--speed=0.75")
+    );
+    core.reset_speed_and_pitch().unwrap();
+    assert!(core.cfg.song_speeds.is_empty());
+    assert_eq!(core.cfg.pitch_semitones, 0.0);
+}
+
+#[test]
+fn queued_songs_play_next() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.play_selected_song(&mut ModalPopup::default());
+    core.queue.extend(["missing.mp3".into(), "c.mp3".into()]);
+    finish_song(&spawned);
+    update_until_played(&mut core, &spawned, 2);
+    assert_eq!(core.selected_song, 2);
+    assert!(core.queue.is_empty());
+    // Once the queue is empty, the playlist behavior is back in charge
+    finish_song(&spawned);
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        !core.mpv_handler.active()
+    });
+    assert_eq!(
+        played(&spawned),
+        [PathBuf::from("/music/a.mp3"), "/music/c.mp3".into()]
+    );
+}
+
+#[test]
+fn stopped_and_cue_track_speeds() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.cfg.remember_song_speed = true;
+    // While stopped, the speed is the one of the selected song
+    core.selected_song = 1;
+    core.set_stopped_speed(1.25);
+    assert_eq!(core.cfg.song_speeds[&PathBuf::from("b.mp3")], 1.25);
+    assert_eq!(core.stopped_speed(), 1.25);
+    assert_eq!(core.cfg.speed, 1.0);
+    // Each track of a cue sheet has its own speed
+    let track = CueTrack {
+        number: 2,
+        title: None,
+        performer: None,
+        chapter: 1,
+        last: false,
+    };
+    let mut modal = ModalPopup::default();
+    core.play_song_with_demuxer(
+        Path::new("/music/album.cue"),
+        None,
+        Some(&track),
+        &mut modal,
+    );
+    let mpv = spawned.borrow()[0].mpv.clone();
+    mpv.property_change("speed", json!(0.8));
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.ipc(|b| b.observed.speed) == Some(0.8)
+    });
+    core.stop_music().unwrap();
+    assert_eq!(core.cfg.song_speeds[&PathBuf::from("album.cue#2")], 0.8);
+}
+
+#[test]
+fn mix_with_file() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.play_selected_song(&mut ModalPopup::default());
+    let mpv = spawned.borrow()[0].mpv.clone();
+    mpv.property_change("track-list", json!([{"id": 1, "type": "audio"}]));
+    mpv.property_change("time-pos", json!(10.0));
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.ipc(|b| b.observed.time_pos) == Some(10.0)
+    });
+    core.mix_with_file("/music/b.mp3".as_ref()).unwrap();
+    // Nothing gets mixed until mpv has loaded the track
+    mpv.property_change(
+        "track-list",
+        json!([{"id": 1, "type": "audio"}, {"id": 2, "type": "audio", "external": true}]),
+    );
+    let graph = "[aid1] volume=0dB [m0]; [aid2] volume=0dB [m1]; \
+                 [m0] [m1] amix=inputs=2:normalize=0 [ao]";
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "lavfi-complex", graph]}))
+    });
+    assert!(
+        mpv.received()
+            .contains(&json!({"command": ["audio-add", "/music/b.mp3"]}))
+    );
+    // Resync after changing the graph
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "time-pos", 10.0]}))
+    });
+    assert_eq!(core.mixer.len(), 2);
+    assert_eq!(core.mix_pending, None);
+}
+
+#[test]
+fn bookmarks() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.play_selected_song(&mut ModalPopup::default());
+    let cue = |name: &str, a| Bookmark {
+        name: name.into(),
+        a,
+        b: None,
+    };
+    core.add_bookmark(cue("Bridge", 60.0));
+    core.add_bookmark(Bookmark {
+        name: "Solo".into(),
+        a: 30.0,
+        b: Some(45.0),
+    });
+    core.add_bookmark(cue("Outro", 90.0));
+    let names: Vec<_> = core.bookmarks().iter().map(|bm| bm.name.as_str()).collect();
+    assert_eq!(names, ["Solo", "Bridge", "Outro"]);
+    assert_eq!(core.cfg.bookmarks[&PathBuf::from("a.mp3")].len(), 3);
+    let mpv = spawned.borrow()[0].mpv.clone();
+    mpv.property_change("time-pos", json!(60.5));
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.ipc(|b| b.observed.time_pos) == Some(60.5)
+    });
+    // Right after a bookmark, going back skips it
+    core.jump_bookmark(false).unwrap();
+    wait_until(|| {
+        let received = mpv.received();
+        received.contains(&json!({"command": ["set_property", "ab-loop-a", 30.0]}))
+            && received.contains(&json!({"command": ["set_property", "ab-loop-b", 45.0]}))
+            && received.contains(&json!({"command": ["set_property", "time-pos", 30.0]}))
+    });
+    core.jump_bookmark(true).unwrap();
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "time-pos", 90.0]}))
+    });
+    core.remove_bookmark(0);
+    core.remove_bookmark(0);
+    core.remove_bookmark(0);
+    assert!(core.cfg.bookmarks.is_empty());
+}
+
+#[test]
+fn practice_mode() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.cfg.practice.count_in = 0.05;
+    core.play_selected_song(&mut ModalPopup::default());
+    let mpv = spawned.borrow()[0].mpv.clone();
+    mpv.property_change("speed", json!(1.1));
+    mpv.property_change("ab-loop-a", json!(20.0));
+    mpv.property_change("ab-loop-b", json!(30.0));
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.ipc(|b| b.observed.ab_loop_a) == Some(Some(20.0))
+    });
+    core.start_practice().unwrap();
+    wait_until(|| {
+        let received = mpv.received();
+        received.contains(&json!({"command": ["set_property", "speed", 0.7]}))
+            && received.contains(&json!({"command": ["set_property", "time-pos", 20.0]}))
+            && received.contains(&json!({"command": ["set_property", "pause", true]}))
+    });
+    for pos in [20.0, 25.0, 29.9, 20.1] {
+        mpv.property_change("time-pos", json!(pos));
+        wait_until(|| {
+            core.update_mpv(&mut modal);
+            core.mpv_handler.ipc(|b| b.observed.time_pos) == Some(pos)
+        });
+    }
+    assert_eq!(core.practice.as_ref().unwrap().iteration, 2);
+    assert!(
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "speed", 0.75]}))
+    );
+    // The count-in resumes by itself
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        mpv.received().last() == Some(&json!({"command": ["set_property", "pause", false]}))
+    });
+    // The ramped up speed is not saved as the speed of the song
+    mpv.property_change("speed", json!(0.75));
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.ipc(|b| b.observed.speed) == Some(0.75)
+    });
+    core.save_mpv_values_to_cfg();
+    assert_eq!(core.cfg.speed, 1.1);
+    core.stop_practice().unwrap();
+    assert!(core.practice.is_none());
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "speed", 1.1]}))
+    });
+}
+
+#[test]
+fn sleep_after_songs() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Continue);
+    core.sleep_timer = Some(SleepTimer::new(SleepTimerKind::AfterSongs(2)));
+    core.play_selected_song(&mut ModalPopup::default());
+    finish_song(&spawned);
+    update_until_played(&mut core, &spawned, 2);
+    let mpv = spawned.borrow()[1].mpv.clone();
+    // Fade out the last song
+    core.cfg.volume = 80;
+    mpv.property_change("volume", json!(80.0));
+    mpv.property_change("duration", json!(100.0));
+    mpv.property_change("time-pos", json!(70.0));
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "volume", 40.0]}))
+    });
+    finish_song(&spawned);
+    update_until_stopped(&mut core, &spawned);
+    assert!(core.sleep_timer.is_none());
+    assert_eq!(core.cfg.volume, 80);
+    assert_eq!(
+        played(&spawned),
+        ["/music/a.mp3", "/music/b.mp3"].map(PathBuf::from)
+    );
+}
+
+#[test]
+fn sleep_after_last_song() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.sleep_timer = Some(SleepTimer::new(SleepTimerKind::AfterSongs(1)));
+    core.play_selected_song(&mut ModalPopup::default());
+    let mpv = spawned.borrow()[0].mpv.clone();
+    core.cfg.volume = 80;
+    mpv.property_change("volume", json!(80.0));
+    mpv.property_change("duration", json!(100.0));
+    mpv.property_change("time-pos", json!(70.0));
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "volume", 40.0]}))
+    });
+    // Nothing plays after it anyway, but the timer is done and the volume is back
+    finish_song(&spawned);
+    update_until_stopped(&mut core, &spawned);
+    assert!(core.sleep_timer.is_none());
+    assert_eq!(core.cfg.volume, 80);
+    // Stopping at the end of the playlist only counts the song once
+    core.sleep_timer = Some(SleepTimer::new(SleepTimerKind::AfterSongs(3)));
+    core.play_selected_song(&mut modal);
+    finish_song(&spawned);
+    update_until_stopped(&mut core, &spawned);
+    core.update_mpv(&mut modal);
+    assert!(matches!(
+        core.sleep_timer.map(|timer| timer.kind),
+        Some(SleepTimerKind::AfterSongs(2))
+    ));
+}
+
+#[test]
+fn fade_in() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.start_fade_in(60, 0.05);
+    core.play_selected_song(&mut ModalPopup::default());
+    assert!(
+        spawned.borrow()[0]
+            .args
+            .iter()
+            .any(|arg| arg == "--volume=0")
+    );
+    let mpv = spawned.borrow()[0].mpv.clone();
+    let mut modal = ModalPopup::default();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.fade_in.is_none()
+    });
+    assert_eq!(core.cfg.volume, 60);
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "volume", 60.0]}))
+    });
+}
+
+#[test]
+fn fade_in_leaves_other_songs_alone() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.cfg.volume = 40;
+    let mut modal = ModalPopup::default();
+    core.play_selected_song(&mut modal);
+    let old_mpv = spawned.borrow()[0].mpv.clone();
+    old_mpv.property_change("volume", json!(40.0));
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.observed().is_some_and(|o| o.volume == 40)
+    });
+    // The song of the alarm isn't playing yet
+    core.start_fade_in(60, 10.0);
+    core.update_mpv(&mut modal);
+    assert_eq!(core.cfg.volume, 40);
+    assert!(!(old_mpv.received().iter()).any(|cmd| cmd["command"][1] == "volume"));
+    core.selected_song = 1;
+    core.play_selected_song(&mut modal);
+    assert!(
+        spawned.borrow()[1]
+            .args
+            .iter()
+            .any(|arg| arg == "--volume=0")
+    );
+    // Stopping halfway through saves the volume faded to, and ends the fade-in
+    let mpv = spawned.borrow()[1].mpv.clone();
+    mpv.property_change("volume", json!(5.0));
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.observed().is_some_and(|o| o.volume == 5)
+    });
+    core.stop_music().unwrap();
+    assert_eq!(core.cfg.volume, 60);
+    assert!(core.fade_in.is_none());
+}
+
+#[test]
+fn change_volume() {
+    let (mut core, spawned) = test_core(PlaylistBehavior::Stop);
+    core.cfg.volume = 50;
+    // Without mpv running, only the config changes
+    core.change_volume(-60).unwrap();
+    assert_eq!(core.cfg.volume, 0);
+    let mut modal = ModalPopup::default();
+    core.play_selected_song(&mut modal);
+    let mpv = spawned.borrow()[0].mpv.clone();
+    wait_until(|| {
+        core.update_mpv(&mut modal);
+        core.mpv_handler.observed().is_some()
+    });
+    core.change_volume(10).unwrap();
+    assert_eq!(core.cfg.volume, 10);
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["set_property", "volume", 10.0]}))
+    });
+}
@@ -0,0 +1,449 @@
+//! Desktop notifications when a song starts, over `org.freedesktop.Notifications`
+
+use {
+    super::App,
+    crate::{cover_art, logln, util::result_ext::LogErrExt as _},
+    crossbeam_channel::{Receiver, Sender},
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::{
+            Arc,
+            atomic::{AtomicU32, Ordering},
+        },
+        time::{Duration, Instant},
+    },
+    zbus::{
+        blocking::{Connection, Proxy},
+        zvariant::Value,
+    },
+};
+
+/// Least time between two notifications, so skipping through songs doesn't flood the desktop
+const MIN_INTERVAL: Duration = Duration::from_secs(3);
+/// How long to wait for the tags of a new song, before notifying without them
+const TAG_WAIT: Duration = Duration::from_secs(1);
+/// How long to wait before trying to connect to the notification daemon again
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Buttons on the notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationAction {
+    Next,
+    TogglePause,
+}
+
+impl NotificationAction {
+    const ALL: [Self; 2] = [Self::Next, Self::TogglePause];
+    fn key(self) -> &'static str {
+        match self {
+            Self::Next => "next",
+            Self::TogglePause => "pause",
+        }
+    }
+    fn label(self) -> &'static str {
+        match self {
+            Self::Next => "Next",
+            Self::TogglePause => "Pause",
+        }
+    }
+    fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.key() == key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    /// The song it's about, for finding its cover art
+    pub song: PathBuf,
+}
+
+/// Shows notifications. The notification daemon of the desktop, or a stand-in.
+pub trait Daemon: Send + 'static {
+    fn notify(&mut self, notification: &Notification, icon: Option<&Path>) -> anyhow::Result<()>;
+}
+
+pub struct Notifier {
+    /// Notifications are sent from a thread, since finding cover art can take a while
+    sender: Sender<Notification>,
+    actions: Receiver<NotificationAction>,
+    /// The song that was notified about last, or is waiting to be
+    song: Option<PathBuf>,
+    /// When `song` started, if it wasn't notified about yet
+    pending_since: Option<Instant>,
+    last_sent: Option<Instant>,
+}
+
+impl Notifier {
+    /// Show notifications with the notification daemon of the desktop
+    pub fn establish() -> Self {
+        let (action_send, action_recv) = crossbeam_channel::unbounded();
+        let connect = move || DbusDaemon::new(&Connection::session()?, action_send.clone());
+        Self::connecting_with(connect, action_recv)
+    }
+    /// Show notifications with `daemon`, which reports clicked buttons to `actions`
+    #[cfg(test)]
+    pub fn with_daemon(daemon: impl Daemon, actions: Receiver<NotificationAction>) -> Self {
+        let mut daemon = Some(daemon);
+        let connect = move || {
+            daemon
+                .take()
+                .ok_or_else(|| anyhow::anyhow!("Daemon is gone"))
+        };
+        Self::connecting_with(connect, actions)
+    }
+    /// Show notifications with the daemon `connect` returns, which reports clicked buttons
+    /// to `actions`.
+    ///
+    /// Connecting happens on the notification thread, so a missing daemon can't block the ui.
+    fn connecting_with<D: Daemon>(
+        mut connect: impl FnMut() -> anyhow::Result<D> + Send + 'static,
+        actions: Receiver<NotificationAction>,
+    ) -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded::<Notification>();
+        std::thread::spawn(move || {
+            let mut daemon = None;
+            let mut last_attempt: Option<Instant> = None;
+            for notification in receiver {
+                // The daemon might not be running yet, so keep trying every once in a while
+                if daemon.is_none() && last_attempt.is_none_or(|at| at.elapsed() >= RETRY_INTERVAL)
+                {
+                    last_attempt = Some(Instant::now());
+                    match connect() {
+                        Ok(connected) => daemon = Some(connected),
+                        Err(e) => logln!("Failed to connect to the notification daemon: {e}"),
+                    }
+                }
+                let Some(daemon) = &mut daemon else {
+                    continue;
+                };
+                let icon = cover_art::find(&notification.song);
+                daemon
+                    .notify(&notification, icon.as_deref())
+                    .log_err("Failed to show notification");
+            }
+        });
+        Self {
+            sender,
+            actions,
+            song: None,
+            pending_since: None,
+            last_sent: None,
+        }
+    }
+    /// Tell which song is playing. Returns it if it's time to notify about it.
+    pub fn due(&mut self, song: Option<&Path>, tags_loaded: bool, now: Instant) -> Option<PathBuf> {
+        if song != self.song.as_deref() {
+            self.song = song.map(Path::to_path_buf);
+            self.pending_since = song.map(|_| now);
+        }
+        let since = self.pending_since?;
+        let waited = tags_loaded || now.duration_since(since) >= TAG_WAIT;
+        let rested = self
+            .last_sent
+            .is_none_or(|last| now.duration_since(last) >= MIN_INTERVAL);
+        if !(waited && rested) {
+            return None;
+        }
+        self.pending_since = None;
+        self.last_sent = Some(now);
+        self.song.clone()
+    }
+    pub fn send(&self, notification: Notification) {
+        self.sender
+            .send(notification)
+            .log_err("Failed to send notification to thread");
+    }
+    /// A button that was clicked on a notification
+    pub fn action(&self) -> Option<NotificationAction> {
+        self.actions.try_recv().ok()
+    }
+}
+
+struct DbusDaemon {
+    proxy: Proxy<'static>,
+    /// Id of the last notification, so the next one replaces it instead of piling up
+    last_id: Arc<AtomicU32>,
+}
+
+impl DbusDaemon {
+    fn new(conn: &Connection, actions: Sender<NotificationAction>) -> anyhow::Result<Self> {
+        let proxy = Proxy::new(
+            conn,
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+        )?;
+        let last_id = Arc::new(AtomicU32::new(0));
+        let signals = proxy.receive_signal("ActionInvoked")?;
+        let our_id = Arc::clone(&last_id);
+        std::thread::spawn(move || {
+            for msg in signals {
+                let body = msg.body();
+                let Ok((id, key)) = body.deserialize::<(u32, String)>() else {
+                    continue;
+                };
+                // Other applications' notifications are none of our business
+                if id != our_id.load(Ordering::Relaxed) {
+                    continue;
+                }
+                if let Some(action) = NotificationAction::from_key(&key)
+                    && actions.send(action).is_err()
+                {
+                    break;
+                }
+            }
+        });
+        Ok(Self { proxy, last_id })
+    }
+}
+
+impl Daemon for DbusDaemon {
+    fn notify(&mut self, notification: &Notification, icon: Option<&Path>) -> anyhow::Result<()> {
+        let actions: Vec<&str> = (NotificationAction::ALL.iter())
+            .flat_map(|action| [action.key(), action.label()])
+            .collect();
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        if let Some(icon) = icon {
+            hints.insert("image-path", icon.to_string_lossy().into_owned().into());
+        }
+        let id: u32 = self.proxy.call(
+            "Notify",
+            &(
+                "mpvfrog",
+                self.last_id.load(Ordering::Relaxed),
+                "",
+                &notification.summary,
+                &notification.body,
+                actions,
+                hints,
+                // Expire as the notification server sees fit
+                -1_i32,
+            ),
+        )?;
+        self.last_id.store(id, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl App {
+    /// Notify about new songs, and handle the buttons clicked on notifications
+    pub(super) fn update_notifications(&mut self) {
+        if !self.core.cfg.notifications {
+            self.notifier = None;
+            return;
+        }
+        let song = self.status().path;
+        let tags_loaded =
+            (self.core.mpv_handler.observed()).is_some_and(|o| !o.metadata.is_empty());
+        let notifier = self.notifier.get_or_insert_with(Notifier::establish);
+        let action = notifier.action();
+        let due = notifier.due(song.as_deref(), tags_loaded, Instant::now());
+        match action {
+            Some(NotificationAction::Next) => self.core.play_next(&mut self.modal),
+            Some(NotificationAction::TogglePause) => {
+                self.core.play_or_toggle_pause(&mut self.modal);
+            }
+            None => {}
+        }
+        if let Some(song) = due
+            && let Some(notifier) = &self.notifier
+        {
+            notifier.send(Notification {
+                summary: self.render_template("{title}"),
+                body: self.render_template("{artist}"),
+                song,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+struct FakeDaemon(Sender<(Notification, Option<PathBuf>)>);
+
+#[cfg(test)]
+impl Daemon for FakeDaemon {
+    fn notify(&mut self, notification: &Notification, icon: Option<&Path>) -> anyhow::Result<()> {
+        self.0
+            .send((notification.clone(), icon.map(Path::to_path_buf)))?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_notifier() {
+    let (shown_send, shown) = crossbeam_channel::unbounded();
+    let (action_send, action_recv) = crossbeam_channel::unbounded();
+    let mut notifier = Notifier::with_daemon(FakeDaemon(shown_send), action_recv);
+    let start = Instant::now();
+    let at = |secs: f64| start + Duration::from_secs_f64(secs);
+    let (a, b, c) = (
+        Path::new("/music/a.mp3"),
+        Path::new("/music/b.mp3"),
+        Path::new("/music/c.mp3"),
+    );
+    assert_eq!(notifier.due(None, false, at(0.0)), None);
+    // Waits for the tags for a while
+    assert_eq!(notifier.due(Some(a), false, at(0.0)), None);
+    assert_eq!(notifier.due(Some(a), false, at(1.0)), Some(a.into()));
+    assert_eq!(notifier.due(Some(a), true, at(1.5)), None);
+    // Too soon after the last one, so b is skipped for c
+    assert_eq!(notifier.due(Some(b), true, at(2.0)), None);
+    assert_eq!(notifier.due(Some(c), true, at(3.5)), None);
+    assert_eq!(notifier.due(Some(c), true, at(4.0)), Some(c.into()));
+    let notification = Notification {
+        summary: "Song".into(),
+        body: "The Band".into(),
+        song: c.into(),
+    };
+    notifier.send(notification.clone());
+    let timeout = Duration::from_secs(5);
+    assert_eq!(shown.recv_timeout(timeout).unwrap(), (notification, None));
+    action_send.send(NotificationAction::Next).unwrap();
+    assert_eq!(notifier.action(), Some(NotificationAction::Next));
+    assert_eq!(notifier.action(), None);
+    assert_eq!(
+        NotificationAction::from_key("pause"),
+        Some(NotificationAction::TogglePause)
+    );
+}
+
+/// What [`FakeServer`] was asked to show: replaced id, summary, body, actions, image path
+#[cfg(test)]
+type ShownNotification = (u32, String, String, Vec<String>, Option<String>);
+
+/// Stand-in for the notification daemon of the desktop
+#[cfg(test)]
+struct FakeServer {
+    shown: Sender<ShownNotification>,
+    next_id: u32,
+}
+
+#[cfg(test)]
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl FakeServer {
+    #[expect(
+        clippy::too_many_arguments,
+        reason = "The signature of the D-Bus method"
+    )]
+    fn notify(
+        &mut self,
+        app_name: &str,
+        replaces_id: u32,
+        _app_icon: &str,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, zbus::zvariant::OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        assert_eq!(app_name, "mpvfrog");
+        let image = (hints.get("image-path"))
+            .and_then(|value| <&str>::try_from(&**value).ok())
+            .map(str::to_owned);
+        let _ = self
+            .shown
+            .send((replaces_id, summary, body, actions, image));
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// Kills the bus when the test is over, even if it fails
+#[cfg(test)]
+struct PrivateBus(std::process::Child);
+
+#[cfg(test)]
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_dbus_daemon() {
+    use std::io::BufRead as _;
+    // A private bus, so the test doesn't show anything on the desktop
+    let spawned = std::process::Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(std::process::Stdio::piped())
+        .spawn();
+    let mut bus = match spawned {
+        Ok(child) => PrivateBus(child),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!("Skipping test_dbus_daemon, dbus-daemon isn't installed");
+            return;
+        }
+        Err(e) => panic!("Failed to run dbus-daemon: {e}"),
+    };
+    let mut address = String::new();
+    std::io::BufReader::new(bus.0.stdout.take().unwrap())
+        .read_line(&mut address)
+        .unwrap();
+    let connect = || zbus::blocking::connection::Builder::address(address.trim()).unwrap();
+    let (shown_send, shown) = crossbeam_channel::unbounded();
+    let server = connect()
+        .name("org.freedesktop.Notifications")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/Notifications",
+            FakeServer {
+                shown: shown_send,
+                next_id: 6,
+            },
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+    let (action_send, action_recv) = crossbeam_channel::unbounded();
+    let mut daemon = DbusDaemon::new(&connect().build().unwrap(), action_send).unwrap();
+    let notification = Notification {
+        summary: "Song".into(),
+        body: "The Band".into(),
+        song: "/music/song.mp3".into(),
+    };
+    let timeout = Duration::from_secs(5);
+    let actions: Vec<String> = ["next", "Next", "pause", "Pause"].map(Into::into).into();
+    daemon
+        .notify(&notification, Some(Path::new("/music/cover.jpg")))
+        .unwrap();
+    assert_eq!(
+        shown.recv_timeout(timeout).unwrap(),
+        (
+            0,
+            "Song".into(),
+            "The Band".into(),
+            actions.clone(),
+            Some("/music/cover.jpg".into())
+        )
+    );
+    // The next one replaces it
+    daemon.notify(&notification, None).unwrap();
+    assert_eq!(
+        shown.recv_timeout(timeout).unwrap(),
+        (7, "Song".into(), "The Band".into(), actions, None)
+    );
+    let invoke = |id: u32, key: &str| {
+        server
+            .emit_signal(
+                None::<&str>,
+                "/org/freedesktop/Notifications",
+                "org.freedesktop.Notifications",
+                "ActionInvoked",
+                &(id, key),
+            )
+            .unwrap();
+    };
+    // Buttons on the notifications of other applications are ignored
+    invoke(7, "next");
+    invoke(8, "unknown");
+    invoke(8, "pause");
+    assert_eq!(
+        action_recv.recv_timeout(timeout).unwrap(),
+        NotificationAction::TogglePause
+    );
+    assert!(action_recv.try_recv().is_err());
+}
@@ -0,0 +1,130 @@
+use {
+    super::App,
+    crate::{
+        remote::PlayState, template, time_fmt::ShortTimeFmt, util::result_ext::LogErrExt as _,
+    },
+    rustix::{fs::OFlags, io::Errno},
+    std::{
+        ffi::OsString,
+        fs::OpenOptions,
+        io::{ErrorKind, Write as _},
+        os::unix::fs::{FileTypeExt as _, OpenOptionsExt as _},
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+};
+
+/// How often the status file can be rewritten
+const STATUS_FILE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps the status file up to date
+pub struct StatusFile {
+    last_write: Instant,
+    /// What was written last time, so it's only rewritten when it changes
+    text: String,
+}
+
+impl Default for StatusFile {
+    fn default() -> Self {
+        Self {
+            last_write: Instant::now(),
+            text: String::new(),
+        }
+    }
+}
+
+impl App {
+    /// Render a template with the variables describing what's playing
+    pub(crate) fn render_template(&self, template: &str) -> String {
+        template::render(template, |name| self.template_var(name))
+    }
+    fn template_var(&self, name: &str) -> Option<String> {
+        let observed = self.core.mpv_handler.observed();
+        let tag = |key| observed.and_then(|o| o.tag(key)).map(str::to_owned);
+        let value = match name {
+            "file" => self.currently_playing_name().unwrap_or_default().to_owned(),
+            "path" => (self.status().path)
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            "artist" | "album" => tag(name).unwrap_or_default(),
+            // Untagged files at least have a file name
+            "title" => tag("title")
+                .or_else(|| {
+                    let path = self.core.played_path.as_ref()?;
+                    Some(path.file_stem()?.to_string_lossy().into_owned())
+                })
+                .unwrap_or_default(),
+            "state" => match self.status().state {
+                PlayState::Stopped => "stopped",
+                PlayState::Playing => "playing",
+                PlayState::Paused => "paused",
+            }
+            .to_owned(),
+            "pos" => ShortTimeFmt(observed.map_or(0.0, |o| o.time_pos)).to_string(),
+            "dur" => ShortTimeFmt(observed.map_or(0.0, |o| o.duration)).to_string(),
+            "volume" => observed
+                .map_or(self.core.cfg.volume, |o| o.volume)
+                .to_string(),
+            "speed" => format!(
+                "{:.2}",
+                observed.map_or(self.core.stopped_speed(), |o| o.speed)
+            ),
+            // Last line of mpv's output
+            "mpv" => (self.core.mpv_handler.mpv_output().lines().last())
+                .unwrap_or_default()
+                .to_owned(),
+            _ => return None,
+        };
+        Some(value)
+    }
+    /// Rewrite the status file if the status changed, but not too often
+    pub(super) fn update_status_file(&mut self) {
+        let Some(path) = &self.core.cfg.status_file else {
+            return;
+        };
+        if self.status_file.last_write.elapsed() < STATUS_FILE_INTERVAL {
+            return;
+        }
+        let text = self.render_template(&self.core.cfg.status_template);
+        if text == self.status_file.text {
+            return;
+        }
+        self.status_file.last_write = Instant::now();
+        match write_status(path, &text) {
+            Ok(true) => self.status_file.text = text,
+            // Try again next time
+            Ok(false) => {}
+            Err(e) => Err(e).log_err("Failed to write status file"),
+        }
+    }
+}
+
+/// Write a line of status to `path`. Returns false if it's a FIFO nothing reads from.
+fn write_status(path: &Path, text: &str) -> std::io::Result<bool> {
+    let line = format!("{text}\n");
+    if std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_fifo()) {
+        // Opening a FIFO would block until something reads from it, unless it's non-blocking
+        let result = OpenOptions::new()
+            .write(true)
+            .custom_flags(OFlags::NONBLOCK.bits() as i32)
+            .open(path)
+            .and_then(|mut fifo| fifo.write_all(line.as_bytes()));
+        return match result {
+            Ok(()) => Ok(true),
+            Err(e)
+                if e.kind() == ErrorKind::WouldBlock
+                    || e.raw_os_error() == Some(Errno::NXIO.raw_os_error()) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        };
+    }
+    // Replace the file in one go, so readers never see it half written
+    let mut tmp_path = OsString::from(path);
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    std::fs::write(&tmp_path, line)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(true)
+}
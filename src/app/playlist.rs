@@ -1,6 +1,11 @@
 use {
-    crate::{config::Config, logln},
+    crate::{
+        config::Config,
+        cue::{self, CueTrack},
+        logln,
+    },
     std::{
+        borrow::Cow,
         cmp::Ordering,
         path::{Path, PathBuf},
     },
@@ -15,14 +20,50 @@ pub struct Playlist {
 
 pub struct Item {
     pub path: PathBuf,
+    /// Set for the virtual entries of each track in a cue sheet
+    pub cue_track: Option<CueTrack>,
 }
 
 impl Item {
     fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            path,
+            cue_track: None,
+        }
+    }
+    /// Text to show for this item in the playlist
+    pub fn label(&self) -> Cow<'_, str> {
+        match &self.cue_track {
+            Some(track) => Cow::Owned(format!("{} › {}", self.path.display(), track.label())),
+            None => self.path.to_string_lossy(),
+        }
     }
 }
 
+/// One virtual item for each track of a cue sheet.
+///
+/// Falls back to a single item if the sheet can't be read.
+fn cue_items(full_path: &Path, path: PathBuf) -> Vec<Item> {
+    let tracks = match std::fs::read(full_path) {
+        // Cue sheets are often not UTF-8, but we can do with some garbled titles
+        Ok(bytes) => cue::parse_tracks(&String::from_utf8_lossy(&bytes)),
+        Err(e) => {
+            logln!("Failed to read cue sheet {}: {e}", full_path.display());
+            Vec::new()
+        }
+    };
+    if tracks.is_empty() {
+        return vec![Item::new(path)];
+    }
+    tracks
+        .into_iter()
+        .map(|track| Item {
+            path: path.clone(),
+            cue_track: Some(track),
+        })
+        .collect()
+}
+
 fn is_hidden(entry: &walkdir::DirEntry) -> bool {
     entry
         .file_name()
@@ -32,6 +73,17 @@ fn is_hidden(entry: &walkdir::DirEntry) -> bool {
 }
 
 impl Playlist {
+    /// A playlist with the given items, without having to scan a folder
+    #[cfg(test)]
+    pub fn from_paths<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            items: paths
+                .into_iter()
+                .map(|path| Item::new(path.into()))
+                .collect(),
+            walkdir_recv: None,
+        }
+    }
     pub fn start_scan(&mut self, cfg: &Config) {
         let Some(music_folder) = cfg.music_folder.clone() else {
             return;
@@ -70,7 +122,11 @@ impl Playlist {
                         continue;
                     }
                     let path = en_path.strip_prefix(&music_folder).unwrap().to_owned();
-                    items.push(Item::new(path));
+                    if en_path.extension().is_some_and(|ext| ext == "cue") {
+                        items.extend(cue_items(en_path, path));
+                    } else {
+                        items.push(Item::new(path));
+                    }
                     // We don't want to send too often, so we use a counter to limit send frequency
                     if counter.is_multiple_of(500) {
                         // If we can't send, we abort the scanning
@@ -123,7 +179,11 @@ impl Playlist {
         self.walkdir_recv.is_some()
     }
     pub fn sort(&mut self) {
-        self.items.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        // Keep the tracks of cue sheets in order
+        self.items.sort_unstable_by(|a, b| {
+            let track_num = |item: &Item| item.cue_track.as_ref().map(|track| track.number);
+            a.path.cmp(&b.path).then(track_num(a).cmp(&track_num(b)))
+        });
     }
     pub fn get(&self, idx: usize) -> Option<&Item> {
         self.items.get(idx)
@@ -139,7 +199,10 @@ impl Playlist {
         // This relies on the scan thread stopping if it fails to send
         self.walkdir_recv = None;
     }
-    pub fn pos_of_path(&self, path: &Path) -> Option<usize> {
-        self.iter().position(|item| item.path == path)
+    /// Position of the item of `path`, and of `cue_track` if it's a cue sheet
+    pub fn pos_of_item(&self, path: &Path, cue_track: Option<u32>) -> Option<usize> {
+        self.iter().position(|item| {
+            item.path == path && item.cue_track.as_ref().map(|track| track.number) == cue_track
+        })
     }
 }
@@ -0,0 +1,66 @@
+use {crate::config::PracticeSettings, std::time::Instant};
+
+/// How far from the ends of the A-B loop the position can be, and still count as
+/// the loop having wrapped around. Position updates don't land exactly on A or B.
+const WRAP_SLACK: f64 = 0.5;
+
+/// State of practice mode, where the speed goes up each time the A-B loop repeats
+pub struct Practice {
+    /// Starts from 1
+    pub iteration: u32,
+    pub speed: f64,
+    /// Speed from before practice mode, which is restored when it's over
+    pub original_speed: f64,
+    last_pos: Option<f64>,
+    /// While counting in, mpv is paused until this moment
+    pub count_in_until: Option<Instant>,
+}
+
+impl Practice {
+    pub fn new(settings: &PracticeSettings, original_speed: f64) -> Self {
+        Self {
+            iteration: 1,
+            speed: settings.start_speed,
+            original_speed,
+            last_pos: None,
+            count_in_until: None,
+        }
+    }
+    /// Feed the current position. Returns whether the `a`-`b` loop just wrapped around.
+    ///
+    /// Only jumps from the end to the start count, so seeking around doesn't.
+    pub fn loop_wrapped(&mut self, pos: f64, a: f64, b: f64) -> bool {
+        let wrapped = self
+            .last_pos
+            .is_some_and(|last| last >= b - WRAP_SLACK && pos <= a + WRAP_SLACK);
+        self.last_pos = Some(pos);
+        wrapped
+    }
+    /// Go to the next iteration, speeding up until the target speed is reached
+    pub fn next_iteration(&mut self, settings: &PracticeSettings) {
+        self.iteration += 1;
+        self.speed = (self.speed + settings.step).min(settings.target_speed);
+    }
+}
+
+#[test]
+fn test_practice() {
+    let settings = PracticeSettings {
+        start_speed: 0.8,
+        target_speed: 0.9,
+        step: 0.06,
+        count_in: 0.0,
+    };
+    let mut practice = Practice::new(&settings, 1.0);
+    assert!(!practice.loop_wrapped(15.0, 10.0, 20.0));
+    // Seeking back to the start from the middle of the loop doesn't count
+    assert!(!practice.loop_wrapped(10.0, 10.0, 20.0));
+    assert!(!practice.loop_wrapped(19.9, 10.0, 20.0));
+    assert!(practice.loop_wrapped(10.1, 10.0, 20.0));
+    assert!(!practice.loop_wrapped(10.2, 10.0, 20.0));
+    practice.next_iteration(&settings);
+    assert_eq!(practice.iteration, 2);
+    assert!((practice.speed - 0.86).abs() < 1e-9);
+    practice.next_iteration(&settings);
+    assert_eq!(practice.speed, 0.9);
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+/// How long before the sleep timer runs out the volume starts fading
+pub const FADE_SECS: f64 = 60.0;
+
+/// Stops the music at some point, so it doesn't keep playing all night
+pub struct SleepTimer {
+    pub kind: SleepTimerKind,
+    /// The volume from before the fade-out started, to go back to once the timer is done
+    pub fade_from: Option<u8>,
+}
+
+pub enum SleepTimerKind {
+    /// Stop at this moment
+    At(Instant),
+    /// Stop after this many more songs have finished. The current song counts.
+    AfterSongs(u32),
+}
+
+impl SleepTimer {
+    pub fn new(kind: SleepTimerKind) -> Self {
+        Self {
+            kind,
+            fade_from: None,
+        }
+    }
+    pub fn after_minutes(minutes: u32) -> Self {
+        let duration = Duration::from_secs(u64::from(minutes) * 60);
+        Self::new(SleepTimerKind::At(Instant::now() + duration))
+    }
+    /// Seconds left until the timer runs out, if that can be told.
+    ///
+    /// `song_left` is the time left of the current song.
+    pub fn remaining_secs(&self, song_left: Option<f64>) -> Option<f64> {
+        match self.kind {
+            SleepTimerKind::At(until) => Some(
+                until
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64(),
+            ),
+            SleepTimerKind::AfterSongs(1) => song_left,
+            SleepTimerKind::AfterSongs(_) => None,
+        }
+    }
+    /// Count a finished song. Returns whether the timer ran out.
+    pub fn song_finished(&mut self) -> bool {
+        match &mut self.kind {
+            SleepTimerKind::At(_) => false,
+            SleepTimerKind::AfterSongs(left) => {
+                *left = left.saturating_sub(1);
+                *left == 0
+            }
+        }
+    }
+    pub fn label(&self) -> String {
+        match self.kind {
+            SleepTimerKind::At(until) => {
+                let secs = until.saturating_duration_since(Instant::now()).as_secs();
+                format!("💤 Sleep in {}:{:02}", secs / 60, secs % 60)
+            }
+            SleepTimerKind::AfterSongs(1) => "💤 Sleep after this song".into(),
+            SleepTimerKind::AfterSongs(n) => format!("💤 Sleep after {n} songs"),
+        }
+    }
+}
+
+/// Volume to play at with `remaining` seconds left, fading out from `from`
+pub fn fade_volume(from: u8, remaining: f64) -> u8 {
+    (f64::from(from) * (remaining / FADE_SECS).clamp(0.0, 1.0)).round() as u8
+}
+
+#[test]
+fn test_sleep_timer() {
+    let mut timer = SleepTimer::new(SleepTimerKind::AfterSongs(2));
+    assert_eq!(timer.label(), "💤 Sleep after 2 songs");
+    assert_eq!(timer.remaining_secs(Some(30.0)), None);
+    assert!(!timer.song_finished());
+    assert_eq!(timer.remaining_secs(Some(30.0)), Some(30.0));
+    assert!(timer.song_finished());
+    let timer = SleepTimer::after_minutes(10);
+    assert!(timer.remaining_secs(None).unwrap() > 599.0);
+    assert_eq!(fade_volume(100, 90.0), 100);
+    assert_eq!(fade_volume(100, 15.0), 25);
+    assert_eq!(fade_volume(100, 0.0), 0);
+}
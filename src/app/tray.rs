@@ -1,42 +1,121 @@
 use {
-    crate::util::result_ext::LogErrExt,
+    crate::{cover_art::CoverArt, remote::PlayState, util::result_ext::LogErrExt},
     crossbeam_channel::{Receiver, Sender},
-    std::sync::Mutex,
-    zbus::{blocking::connection, interface, object_server::SignalEmitter},
+    std::{path::PathBuf, sync::Mutex},
+    zbus::{blocking::connection, interface, names::BusName, object_server::SignalEmitter},
 };
 
+/// Width and height of the cover art tray icon
+const COVER_ICON_SIZE: usize = 64;
+/// How much a notch of scrolling on the icon changes the volume
+const SCROLL_VOLUME_STEP: i32 = 5;
+/// How many seconds a notch of horizontal scrolling on the icon seeks
+const SCROLL_SEEK_STEP: f64 = 5.0;
+
 pub struct AppTray {
     pub event_flags: EventFlags,
     pub sender: Sender<AppToTrayMsg>,
     receiver: Receiver<TrayToAppMsg>,
     pub conn: connection::Connection,
+    /// Image of the cover art and the play state the icon shows, if any
+    icon_key: (Option<PathBuf>, Option<PlayState>),
 }
 
 impl AppTray {
     pub fn update(&mut self) {
-        if let Ok(msg) = self.receiver.try_recv() {
+        // Scrolling sends lots of messages, so handle all of them
+        while let Ok(msg) = self.receiver.try_recv() {
             match msg {
                 TrayToAppMsg::ShowCtxMenu { x, y } => self.event_flags.ctx_menu = Some((x, y)),
                 TrayToAppMsg::Activate => self.event_flags.activated = true,
+                TrayToAppMsg::SecondaryActivate => self.event_flags.toggle_pause ^= true,
+                TrayToAppMsg::Scroll { delta, horizontal } => {
+                    let flags = &mut self.event_flags;
+                    if horizontal {
+                        let notches = scroll_notches(&mut flags.hscroll_remainder, delta);
+                        flags.seek_by += f64::from(notches) * SCROLL_SEEK_STEP;
+                    } else {
+                        let notches = scroll_notches(&mut flags.scroll_remainder, delta);
+                        flags.volume_change += notches * SCROLL_VOLUME_STEP;
+                    }
+                }
             }
         }
     }
+    /// Show `cover` as the icon (or the frog if `None`), with a badge for `state` if `Some`
+    pub fn set_icon(&mut self, cover: Option<&CoverArt>, state: Option<PlayState>) {
+        let key = (cover.map(|art| art.path.clone()), state);
+        if key == self.icon_key {
+            return;
+        }
+        self.icon_key = key;
+        self.sender
+            .send(AppToTrayMsg::UpdateIcon(icon_pixmaps(cover, state)))
+            .log_err("Failed to send icon to tray");
+        self.conn
+            .emit_signal(
+                None::<BusName>,
+                "/StatusNotifierItem",
+                "org.kde.StatusNotifierItem",
+                "NewIcon",
+                &(),
+            )
+            .log_err("Failed to emit NewIcon");
+    }
 }
 
 pub enum TrayToAppMsg {
-    ShowCtxMenu { x: i32, y: i32 },
+    ShowCtxMenu {
+        x: i32,
+        y: i32,
+    },
     Activate,
+    /// Middle click
+    SecondaryActivate,
+    Scroll {
+        delta: i32,
+        horizontal: bool,
+    },
+}
+
+/// Notches of the mouse wheel a scroll `delta` amounts to, along with the `remainder` of
+/// earlier deltas that didn't make up a whole notch.
+///
+/// Some hosts send 120 per notch like Qt does, others send 1.
+/// Smooth scrolling sends fractions of 120.
+fn scroll_notches(remainder: &mut i32, delta: i32) -> i32 {
+    if delta.abs() == 1 {
+        return delta;
+    }
+    *remainder += delta;
+    let notches = *remainder / 120;
+    *remainder %= 120;
+    notches
 }
 
 #[derive(Debug)]
 pub enum AppToTrayMsg {
     UpdateHoverText(String),
+    UpdateIcon(Pixmaps),
 }
 
 pub struct TrayIface {
     sender: Sender<TrayToAppMsg>,
     receiver: Receiver<AppToTrayMsg>,
     tooltip: Mutex<String>,
+    icon: Mutex<Pixmaps>,
+}
+
+impl TrayIface {
+    /// Apply the updates the app sent
+    fn recv_updates(&self) {
+        while let Ok(msg) = self.receiver.try_recv() {
+            match msg {
+                AppToTrayMsg::UpdateHoverText(s) => *self.tooltip.lock().unwrap() = s,
+                AppToTrayMsg::UpdateIcon(icon) => *self.icon.lock().unwrap() = icon,
+            }
+        }
+    }
 }
 
 impl AppTray {
@@ -52,6 +131,7 @@ impl AppTray {
                     sender: s1,
                     receiver: r2,
                     tooltip: Mutex::new("mpv-frog".into()),
+                    icon: Mutex::new(icon_pixmaps(None, None)),
                 },
             )?
             .build()?;
@@ -67,6 +147,7 @@ impl AppTray {
             sender: s2,
             receiver: r1,
             conn,
+            icon_key: (None, None),
         })
     }
 }
@@ -76,11 +157,23 @@ pub struct EventFlags {
     pub activated: bool,
     pub quit_clicked: bool,
     pub ctx_menu: Option<(i32, i32)>,
+    pub toggle_pause: bool,
+    /// Volume change scrolled since the last update
+    pub volume_change: i32,
+    /// Seconds to seek, scrolled horizontally since the last update
+    pub seek_by: f64,
+    /// Scrolling that doesn't make up a whole notch yet
+    scroll_remainder: i32,
+    hscroll_remainder: i32,
 }
 
 impl EventFlags {
     pub fn take(&mut self) -> Self {
-        std::mem::take(self)
+        let taken = std::mem::take(self);
+        // Partial notches count towards the next ones
+        self.scroll_remainder = taken.scroll_remainder;
+        self.hscroll_remainder = taken.hscroll_remainder;
+        taken
     }
 }
 
@@ -96,6 +189,75 @@ macro_rules! icon {
     };
 }
 
+/// Icons as (width, height, ARGB32 pixels in network byte order)
+pub type Pixmaps = Vec<(i32, i32, Vec<u8>)>;
+
+/// The cover art or the frog, with a badge in the corner showing `state`
+fn icon_pixmaps(cover: Option<&CoverArt>, state: Option<PlayState>) -> Pixmaps {
+    let mut icons: Pixmaps = match cover {
+        Some(art) => {
+            let size = COVER_ICON_SIZE as i32;
+            vec![(size, size, art.argb32(COVER_ICON_SIZE))]
+        }
+        None => {
+            let frog: SniIcon = icon!();
+            (frog.iter())
+                .map(|&(w, h, pixels)| (w, h, pixels.to_vec()))
+                .collect()
+        }
+    };
+    if let Some(state) = state {
+        for (size, _, pixels) in &mut icons {
+            draw_state_badge(pixels, *size as usize, state);
+        }
+    }
+    icons
+}
+
+/// Draw a ▶, ⏸ or ⏹ badge over the bottom right quarter of a square ARGB32 icon
+fn draw_state_badge(pixels: &mut [u8], size: usize, state: PlayState) {
+    /// Subsamples per pixel side, for smooth edges
+    const SUB: usize = 4;
+    let badge = size / 2;
+    let inside = |u: f32, v: f32| -> (bool, bool) {
+        let disc = (u - 0.5).powi(2) + (v - 0.5).powi(2) <= 0.25;
+        let glyph = match state {
+            PlayState::Playing => u >= 0.35 && (v - 0.5).abs() <= (0.75 - u) * 0.6,
+            PlayState::Paused => {
+                ((0.3..=0.45).contains(&u) || (0.55..=0.7).contains(&u))
+                    && (0.28..=0.72).contains(&v)
+            }
+            PlayState::Stopped => (0.32..=0.68).contains(&u) && (0.32..=0.68).contains(&v),
+        };
+        (disc, glyph)
+    };
+    for y in 0..badge {
+        for x in 0..badge {
+            let (mut disc, mut glyph) = (0, 0);
+            for sy in 0..SUB {
+                for sx in 0..SUB {
+                    let u = (x * SUB + sx) as f32 / (badge * SUB) as f32;
+                    let v = (y * SUB + sy) as f32 / (badge * SUB) as f32;
+                    let (in_disc, in_glyph) = inside(u, v);
+                    disc += usize::from(in_disc);
+                    glyph += usize::from(in_disc && in_glyph);
+                }
+            }
+            let idx = ((size - badge + y) * size + size - badge + x) * 4;
+            let px: &mut [u8; 4] = (&mut pixels[idx..idx + 4]).try_into().unwrap();
+            let [alpha, rgb @ ..] = px;
+            // Dark disc with a white glyph on it, blended over the icon
+            let disc_alpha = disc as f32 / (SUB * SUB) as f32 * 0.85;
+            let glyph_alpha = glyph as f32 / (SUB * SUB) as f32;
+            for c in rgb {
+                let dark = f32::from(*c) * (1.0 - disc_alpha) + 24.0 * disc_alpha;
+                *c = (dark * (1.0 - glyph_alpha) + 255.0 * glyph_alpha) as u8;
+            }
+            *alpha = (*alpha).max((disc_alpha * 255.0) as u8);
+        }
+    }
+}
+
 #[interface(name = "org.kde.StatusNotifierItem")]
 impl TrayIface {
     #[zbus(property)]
@@ -115,22 +277,23 @@ impl TrayIface {
         "Active"
     }
     #[zbus(property)]
-    fn icon_pixmap(&self) -> SniIcon {
-        icon!()
+    fn icon_pixmap(&self) -> Pixmaps {
+        self.recv_updates();
+        self.icon.lock().unwrap().clone()
     }
     #[zbus(property)]
     fn tool_tip(&self) -> (&'static str, SniIcon, String, &'static str) {
         let icon_name = "preferences-desktop-notification";
         let icon: SniIcon = &[];
-        let mut tooltip = self.tooltip.lock().unwrap();
-        if let Ok(msg) = self.receiver.try_recv() {
-            match msg {
-                AppToTrayMsg::UpdateHoverText(s) => *tooltip = s,
-            }
-        }
+        self.recv_updates();
         // Unfortunately content seems to be ignored (by at least lxqt-panel)
         let content = "";
-        (icon_name, icon, tooltip.clone(), content)
+        (
+            icon_name,
+            icon,
+            self.tooltip.lock().unwrap().clone(),
+            content,
+        )
     }
     /// Needed so all tray providers enable "Activate"
     #[zbus(property)]
@@ -147,8 +310,75 @@ impl TrayIface {
             .send(TrayToAppMsg::Activate)
             .log_err("Failed to send context menu msg");
     }
+    fn secondary_activate(&self, _x: i32, _y: i32) {
+        self.sender
+            .send(TrayToAppMsg::SecondaryActivate)
+            .log_err("Failed to send secondary activate msg");
+    }
+    fn scroll(&self, delta: i32, orientation: &str) {
+        let horizontal = orientation.eq_ignore_ascii_case("horizontal");
+        self.sender
+            .send(TrayToAppMsg::Scroll { delta, horizontal })
+            .log_err("Failed to send scroll msg");
+    }
     #[zbus(signal)]
     async fn new_tool_tip(_ctx: &SignalEmitter<'_>) -> zbus::Result<()>;
     #[zbus(signal)]
     async fn new_title(_ctx: &SignalEmitter<'_>) -> zbus::Result<()>;
+    #[zbus(signal)]
+    async fn new_icon(_ctx: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+#[test]
+fn test_state_badge() {
+    let size = 32;
+    let blank = vec![0; size * size * 4];
+    let badged = |state| {
+        let mut pixels = blank.clone();
+        draw_state_badge(&mut pixels, size, state);
+        pixels
+    };
+    let at = |pixels: &[u8], x: usize, y: usize| {
+        let idx = (y * size + x) * 4;
+        [
+            pixels[idx],
+            pixels[idx + 1],
+            pixels[idx + 2],
+            pixels[idx + 3],
+        ]
+    };
+    let playing = badged(PlayState::Playing);
+    // The top left quarter is left alone
+    assert_eq!(playing[..size * 4 * size / 2], blank[..size * 4 * size / 2]);
+    // The middle of the badge is white, its corner is untouched
+    assert_eq!(at(&playing, 24, 24), [216, 255, 255, 255]);
+    assert_eq!(at(&playing, 16, 16), [0; 4]);
+    let paused = badged(PlayState::Paused);
+    // Gap between the pause bars
+    assert_eq!(at(&paused, 24, 24), [216, 20, 20, 20]);
+    assert_ne!(badged(PlayState::Stopped), playing);
+}
+
+#[test]
+fn test_scroll_notches() {
+    let mut rest = 0;
+    assert_eq!(scroll_notches(&mut rest, 120), 1);
+    assert_eq!(scroll_notches(&mut rest, -240), -2);
+    assert_eq!(scroll_notches(&mut rest, 1), 1);
+    assert_eq!(scroll_notches(&mut rest, -1), -1);
+    assert_eq!(scroll_notches(&mut rest, 0), 0);
+    // Smooth scrolling adds up to whole notches
+    for _ in 0..7 {
+        assert_eq!(scroll_notches(&mut rest, -15), 0);
+    }
+    assert_eq!(scroll_notches(&mut rest, -15), -1);
+    assert_eq!(scroll_notches(&mut rest, 150), 1);
+    assert_eq!(rest, 30);
+    // Also across updates
+    let mut flags = EventFlags {
+        scroll_remainder: rest,
+        ..Default::default()
+    };
+    flags.take();
+    assert_eq!(flags.scroll_remainder, 30);
 }
@@ -1,33 +1,52 @@
+mod alarms_window;
 mod color_theme_window;
 mod custom_demuxers_window;
+mod equalizer_window;
+mod loudness_window;
+mod mixer_window;
 mod mpv_console_window;
+mod mpv_settings_window;
+mod status_output_window;
+mod tracks_window;
 
 use {
     self::custom_demuxers_window::CustomDemuxersWindow,
-    super::{Core, LOG, ModalPopup, PlaylistBehavior},
+    super::{
+        Core, LOG, ModalPopup, PlaylistBehavior,
+        sleep_timer::{SleepTimer, SleepTimerKind},
+    },
     crate::{
-        config::CustomDemuxerEntry,
-        ipc::Bridge,
-        mpv_handler::ActivePtyInput,
+        config::{Bookmark, CustomDemuxerEntry, SpeedMode},
+        cover_art::{self, CoverArt},
+        ipc::{Bridge, ChapterEntry},
+        mpv_handler::{ActivePtyInput, TimeInfo},
         time_fmt::FfmpegTimeFmt,
         util::{
-            egui_ext::EguiResponseExt as _,
-            result_ext::ResultModalExt as _,
-            str_ext::{StrExt as _, trim_lines},
+            egui_ext::EguiResponseExt as _, result_ext::ResultModalExt as _, str_ext::trim_lines,
         },
     },
+    alarms_window::AlarmsWindow,
     anyhow::Context as _,
     color_theme_window::ColorThemeWindow,
     egui_colors::{Colorix, tokens::ThemeColor},
     egui_sf2g::egui::{
-        self, Align, Button, CentralPanel, ComboBox, Context, ScrollArea, TextEdit,
+        self, Align, Button, CentralPanel, ComboBox, Context, Rangef, Rect, ScrollArea, Sense,
+        Stroke, TextEdit, TextureHandle, TextureOptions,
         epaint::text::{FontInsert, FontPriority, InsertFontFamily},
         scroll_area::ScrollSource,
-        text::CharIndex,
     },
+    equalizer_window::EqualizerWindow,
     fuzzy_matcher::{FuzzyMatcher as _, skim::SkimMatcherV2},
+    loudness_window::LoudnessWindow,
+    mixer_window::MixerWindow,
     mpv_console_window::MpvConsoleWindow,
-    std::{borrow::Cow, path::Path},
+    mpv_settings_window::MpvSettingsWindow,
+    status_output_window::StatusOutputWindow,
+    std::{
+        borrow::Cow,
+        path::{Path, PathBuf},
+    },
+    tracks_window::TracksWindow,
 };
 
 #[derive(Default)]
@@ -35,6 +54,13 @@ struct Windows {
     custom_demuxers: CustomDemuxersWindow,
     color_theme: ColorThemeWindow,
     mpv_console: MpvConsoleWindow,
+    mpv_settings: MpvSettingsWindow,
+    equalizer: EqualizerWindow,
+    loudness: LoudnessWindow,
+    tracks: TracksWindow,
+    mixer: MixerWindow,
+    alarms: AlarmsWindow,
+    status_output: StatusOutputWindow,
 }
 
 impl Windows {
@@ -42,6 +68,13 @@ impl Windows {
         self.custom_demuxers.update(core, ctx);
         self.color_theme.update(core, ctx, colorix);
         self.mpv_console.update(core, ctx);
+        self.mpv_settings.update(core, ctx);
+        self.equalizer.update(core, ctx);
+        self.loudness.update(core, ctx);
+        self.tracks.update(core, ctx);
+        self.mixer.update(core, ctx);
+        self.alarms.update(core, ctx);
+        self.status_output.update(core, ctx);
     }
 }
 
@@ -59,6 +92,8 @@ pub struct Ui {
     filtered_entries: Vec<usize>,
     ab_loop_a: f64,
     ab_loop_b: f64,
+    /// Name for the next bookmark
+    bookmark_name: String,
     /// If `Some`, focus on the playlist item with that index
     pub focus_on: Option<usize>,
     /// Which filtered entry is selected (up and down keys while filter box is focused)
@@ -141,9 +176,61 @@ impl Ui {
                 if ui.button("🎶 Custom demuxers...").clicked() {
                     self.windows.custom_demuxers.open ^= true;
                 }
+                if ui.button("⚙ Mpv settings...").clicked() {
+                    self.windows.mpv_settings.open ^= true;
+                }
+                if ui.button("🎚 Equalizer...").clicked() {
+                    self.windows.equalizer.open ^= true;
+                }
+                if ui.button("📢 Loudness...").clicked() {
+                    self.windows.loudness.open ^= true;
+                }
+                if ui.button("🎞 Tracks...").clicked() {
+                    self.windows.tracks.open ^= true;
+                }
+                if ui.button("🎛 Mixer...").clicked() {
+                    self.windows.mixer.open ^= true;
+                }
+                if ui.button("⏰ Alarms...").clicked() {
+                    self.windows.alarms.open ^= true;
+                }
+                if ui.button("📝 Status output...").clicked() {
+                    self.windows.status_output.open ^= true;
+                }
                 if ui.button("💎 Color theme config").clicked() {
                     self.windows.color_theme.open ^= true;
                 }
+                ui.checkbox(&mut core.cfg.waveform, "Waveform seek bar")
+                    .on_hover_text("Needs ffmpeg. Applies to the next song.");
+                let mut level_meter = core.cfg.level_meter;
+                if ui
+                    .checkbox(&mut level_meter, "Level meter")
+                    .on_hover_text("Measure the audio levels with ffmpeg's astats filter")
+                    .changed()
+                {
+                    core.set_level_meter(level_meter)
+                        .err_popup("Level meter error", modal);
+                }
+                ui.add_enabled(
+                    core.cfg.level_meter,
+                    egui::Checkbox::new(&mut core.cfg.level_meter_in_tray, "Level meter in tray"),
+                );
+                ui.checkbox(&mut core.cfg.cover_art, "Cover art")
+                    .on_hover_text("Needs ffmpeg. Applies to the next song.");
+                ui.add_enabled(
+                    core.cfg.cover_art,
+                    egui::Checkbox::new(
+                        &mut core.cfg.cover_art_tray_icon,
+                        "Cover art as tray icon",
+                    ),
+                );
+                ui.checkbox(&mut core.cfg.tray_icon_state, "Play state on tray icon");
+                ui.checkbox(&mut core.cfg.remember_song_speed, "Remember speed per song")
+                    .on_hover_text("Each song starts at the speed it was last played at");
+                ui.checkbox(&mut core.cfg.control_socket, "Control socket")
+                    .on_hover_text("Let scripts control mpvfrog. Takes effect on restart.");
+                ui.checkbox(&mut core.cfg.notifications, "Notifications")
+                    .on_hover_text("Show a desktop notification when a song starts");
                 ui.checkbox(&mut core.cfg.follow_symlinks, "Follow symlinks")
                     .on_hover_text("Follow symbolic links when reading a directory");
                 ui.checkbox(&mut core.cfg.skip_hidden, "Skip hidden entries")
@@ -152,6 +239,20 @@ impl Ui {
                     ui.label("Scan max depth");
                     ui.add(egui::DragValue::new(&mut core.cfg.scan_max_depth).range(1..=50));
                 });
+                #[cfg(feature = "libmpv")]
+                ui.horizontal(|ui| {
+                    use crate::config::BackendKind;
+                    ui.label("Backend");
+                    ComboBox::new("backend_cb", "")
+                        .selected_text(core.cfg.backend.label())
+                        .show_ui(ui, |ui| {
+                            for kind in [BackendKind::Process, BackendKind::Libmpv] {
+                                ui.selectable_value(&mut core.cfg.backend, kind, kind.label());
+                            }
+                        })
+                        .response
+                        .on_hover_text("Takes effect when the next song starts");
+                });
                 if ui.button("🖳 Mpv console").clicked() {
                     self.windows.mpv_console.open ^= true;
                 }
@@ -177,6 +278,14 @@ impl Ui {
                     self.quit_requested = true;
                 }
             });
+            let sleep_label =
+                (core.sleep_timer.as_ref()).map_or_else(|| "💤".into(), SleepTimer::label);
+            ui.menu_button(sleep_label, |ui| {
+                ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
+                sleep_timer_menu_ui(ui, core, modal);
+            })
+            .response
+            .on_hover_text("Sleep timer");
             ui.group(|ui| {
                 match &core.cfg.music_folder {
                     Some(folder) => {
@@ -276,11 +385,9 @@ impl Ui {
             .iter()
             .enumerate()
             .filter_map(|(idx, item)| {
-                item.path.to_str().and_then(|path_str| {
-                    matcher
-                        .fuzzy_match(path_str, &prepared_filter)
-                        .map(|score| (idx, score))
-                })
+                matcher
+                    .fuzzy_match(&item.label(), &prepared_filter)
+                    .map(|score| (idx, score))
             })
             .collect();
         scored_indices.sort_by(|(_, score1), (_, score2)| score1.cmp(score2).reverse());
@@ -293,66 +400,69 @@ impl Ui {
     fn central_panel_ui(&mut self, core: &mut Core, ui: &mut egui::Ui, modal: &mut ModalPopup) {
         let row_h = ui.text_style_height(&egui::TextStyle::Body);
         let mut scroll_to_y = None;
-        let mut out = ScrollArea::vertical()
-            .max_height(200.0)
-            .auto_shrink([false; 2])
-            .id_salt("song_scroll")
-            .scroll_source(ScrollSource::ALL)
-            .show_rows(ui, row_h, self.filtered_entries.len(), |ui, range| {
-                if self.filtered_entries.is_empty() {
-                    let not_shown_count = core.playlist.len();
-                    ui.label(format!("<No results> ({not_shown_count} not shown)"));
-                }
-                for &i in &self.filtered_entries[range] {
-                    let Some(item) = &core.playlist.get(i) else {
-                        ui.label("<oob index error>");
-                        break;
-                    };
-                    let path = &item.path;
-                    let re =
-                        ui.selectable_label(core.selected_song == i, path.display().to_string());
-                    let mut play_with: Option<CustomDemuxerEntry> = None;
-                    re.context_menu(|ui| {
-                        ui.menu_button("Play as", |ui| {
-                            for demux in &core.cfg.custom_demuxers {
-                                if ui.button(&demux.name).clicked() {
-                                    play_with = Some(demux.clone());
+        let mut out = ui
+            .horizontal_top(|ui| {
+                if core.cfg.cover_art
+                    && let Some(art) = &core.cover_art
+                {
+                    cover_art_image(ui, art, 200.0);
+                }
+                ScrollArea::vertical()
+                    .max_height(200.0)
+                    .auto_shrink([false; 2])
+                    .id_salt("song_scroll")
+                    .scroll_source(ScrollSource::ALL)
+                    .show_rows(ui, row_h, self.filtered_entries.len(), |ui, range| {
+                        if self.filtered_entries.is_empty() {
+                            let not_shown_count = core.playlist.len();
+                            ui.label(format!("<No results> ({not_shown_count} not shown)"));
+                        }
+                        for &i in &self.filtered_entries[range] {
+                            let Some(item) = &core.playlist.get(i) else {
+                                ui.label("<oob index error>");
+                                break;
+                            };
+                            let path = &item.path;
+                            let re = ui.selectable_label(core.selected_song == i, item.label());
+                            let mut play_with: Option<CustomDemuxerEntry> = None;
+                            re.context_menu(|ui| {
+                                ui.menu_button("Play as", |ui| {
+                                    for demux in &core.cfg.custom_demuxers {
+                                        if ui.button(&demux.name).clicked() {
+                                            play_with = Some(demux.clone());
+                                        }
+                                    }
+                                });
+                                if ui.button("Mix with current").clicked() {
+                                    let full_path =
+                                        core.cfg.music_folder.as_ref().unwrap().join(path);
+                                    core.mix_with_file(&full_path)
+                                        .err_popup("Failed to add track", modal);
                                 }
+                                if ui.button("Copy full path").clicked() {
+                                    let full_path =
+                                        core.cfg.music_folder.as_ref().unwrap().join(path);
+                                    ui.ctx().copy_text(full_path.to_string_lossy().into_owned());
+                                }
+                            });
+                            if let Some(demux) = play_with
+                                && let Some(path) = core.path_of_song_at_playlist_index(i)
+                            {
+                                core.play_song_with_demuxer(&path, Some(demux), None, modal);
+                            }
+                            if self.focus_on.is_some_and(|idx| idx == i) {
+                                re.scroll_to_me(Some(Align::Center));
+                                self.focus_on = None;
+                            }
+                            if re.clicked() {
+                                core.selected_song = i;
+                                core.play_selected_song(modal);
+                                break;
                             }
-                        });
-                        if ui.button("Mix with current").clicked() {
-                            let full_path = core.cfg.music_folder.as_ref().unwrap().join(path);
-                            core.mpv_handler
-                                .ipc(|b| b.add_audio(full_path.as_os_str().to_str().unwrap()))
-                                .err_popup("Failed to add track", modal);
-                            // FIXME: Due to a bug(?) in mpv/libavfilter, more often than not
-                            // there is a desync unless we seek to 0 first.
-                            core.mpv_handler.ipc(|br| br.seek(0.));
-                            crate::logln!(
-                                "Note: There might be desync when seeking with mixed tracks"
-                            );
-                        }
-                        if ui.button("Copy full path").clicked() {
-                            let full_path = core.cfg.music_folder.as_ref().unwrap().join(path);
-                            ui.ctx().copy_text(full_path.to_string_lossy().into_owned());
                         }
-                    });
-                    if let Some(demux) = play_with
-                        && let Some(path) = core.path_of_song_at_playlist_index(i)
-                    {
-                        core.play_song_with_demuxer(&path, Some(demux), modal);
-                    }
-                    if self.focus_on.is_some_and(|idx| idx == i) {
-                        re.scroll_to_me(Some(Align::Center));
-                        self.focus_on = None;
-                    }
-                    if re.clicked() {
-                        core.selected_song = i;
-                        core.play_selected_song(modal);
-                        break;
-                    }
-                }
-            });
+                    })
+            })
+            .inner;
         if let Some(playlist_idx) = self.focus_on
             && let Some(filtlist_idx) = self
                 .filtered_entries
@@ -406,7 +516,7 @@ impl Ui {
                     }
                 }
                 if ui.add_enabled(active, Button::new("⏹")).clicked() {
-                    core.stop_music();
+                    core.stop_music().err_popup("Stop error", modal);
                 }
                 if ui.add(Button::new(ICO_NEXT)).clicked() {
                     core.play_next(modal);
@@ -442,15 +552,52 @@ impl Ui {
                         }
                     }
                     None => {
-                        ui.add(egui::Slider::new(&mut core.cfg.speed, 0.3..=2.0));
+                        let mut speed = core.stopped_speed();
+                        if ui.add(egui::Slider::new(&mut speed, 0.3..=2.0)).changed() {
+                            core.set_stopped_speed(speed);
+                        }
                     }
                 }
+                let tape = core.cfg.speed_mode == SpeedMode::Tape;
+                if ui
+                    .selectable_label(tape, "📼")
+                    .on_hover_text("Tape mode: pitch changes along with the speed")
+                    .clicked()
+                {
+                    let mode = if tape {
+                        SpeedMode::TimeStretch
+                    } else {
+                        SpeedMode::Tape
+                    };
+                    core.set_speed_mode(mode)
+                        .err_popup("Speed mode change error", modal);
+                }
+                let mut pitch = core.cfg.pitch_semitones;
+                let re = ui
+                    .add(
+                        egui::DragValue::new(&mut pitch)
+                            .range(-12.0..=12.0)
+                            .speed(0.1)
+                            .suffix(" st"),
+                    )
+                    .on_hover_text(
+                        "Pitch shift in semitones. Needs ffmpeg built with rubberband.\n\
+                         Backspace resets speed and pitch.",
+                    );
+                if re.changed() {
+                    core.set_pitch(pitch).err_popup("Pitch change error", modal);
+                }
             });
             if ui.checkbox(&mut core.cfg.video, "video").clicked() {
                 core.mpv_handler
                     .ipc(|b| b.set_video(core.cfg.video))
                     .err_popup("Video set error", modal);
             }
+            ui.label("🎧");
+            audio_device_combo(ui, core, modal, 160.0);
+            if core.cfg.level_meter {
+                level_meter(ui, core, 120.0);
+            }
         });
         ui.horizontal(|ui| {
             if let Some(mut info) = core.mpv_handler.time_info() {
@@ -509,18 +656,43 @@ impl Ui {
                                 FfmpegTimeFmt(b)
                             ));
                         }
+                        ui.separator();
+                        ui.label("Bookmarks");
+                        self.bookmarks_menu_ui(core, ui, modal, info.pos);
+                    });
+                    ui.menu_button("Practice", |ui| {
+                        ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
+                        practice_menu_ui(ui, core, modal);
                     });
                 });
                 let seekable = core
                     .mpv_handler
                     .ipc(|b| b.observed.seekable)
                     .unwrap_or(false);
-                if seekable {
+                let chapters =
+                    (core.mpv_handler.ipc(|b| b.observed.chapters.clone())).unwrap_or_default();
+                let bookmarks = core.bookmarks().to_vec();
+                let markers = Markers {
+                    chapters: &chapters,
+                    bookmarks: &bookmarks,
+                };
+                if seekable && let Some(waveform) = &core.waveform {
+                    let ab_loop = match core.mpv_handler.ab_loop() {
+                        Some((Some(a), Some(b))) => Some((a, b)),
+                        _ => None,
+                    };
+                    if let Some(pos) = waveform_seek_bar(ui, waveform, &info, ab_loop, &markers) {
+                        core.seek(pos).err_popup("Seek error", modal);
+                    }
+                } else if seekable {
                     let mut re = ui.add(
                         egui::Slider::new(&mut info.pos, 0.0..=info.duration)
                             .show_value(false)
                             .trailing_fill(true),
                     );
+                    // The rail of the slider is inset by the radius of its handle
+                    let rail = re.rect.x_range().shrink(re.rect.height() / 2.5);
+                    markers.draw(ui, rail, re.rect.y_range(), info.duration);
                     if let Some(ratio) = re.h_pointer_ratio() {
                         // TODO: This is not 100% accurate, unfortunately
                         re = re.on_hover_text_at_pointer(
@@ -545,20 +717,23 @@ impl Ui {
             let re = ui.selectable_value(&mut self.output_source, OutputSource::Mpv, "Mpv");
             re.context_menu(|ui| {
                 if ui.button("Clear").clicked() {
-                    core.mpv_handler.mpv_term.reset();
+                    core.mpv_handler.output.mpv_term.reset();
                 }
             });
             if re.clicked() {
                 core.mpv_handler.active_pty_input = ActivePtyInput::Mpv;
             }
             let mut demux_enabled = true;
-            if !core.mpv_handler.demuxer_active() && core.mpv_handler.demux_term.is_empty() {
+            if !core.mpv_handler.demuxer_active() && core.mpv_handler.output.demux_term.is_empty() {
                 demux_enabled = false;
             }
-            let tab_name = if core.mpv_handler.demux_cmd_name.is_empty() {
+            let tab_name = if core.mpv_handler.output.demux_cmd_name.is_empty() {
                 Cow::Borrowed("Demuxer")
             } else {
-                Cow::Owned(format!("Demuxer: {}", core.mpv_handler.demux_cmd_name))
+                Cow::Owned(format!(
+                    "Demuxer: {}",
+                    core.mpv_handler.output.demux_cmd_name
+                ))
             };
             let re = ui.add_enabled(
                 demux_enabled,
@@ -566,7 +741,7 @@ impl Ui {
             );
             re.context_menu(|ui| {
                 if ui.button("Clear").clicked() {
-                    core.mpv_handler.demux_term.reset();
+                    core.mpv_handler.output.demux_term.reset();
                 }
             });
             if re.on_disabled_hover_text("No active demuxer").clicked() {
@@ -575,9 +750,24 @@ impl Ui {
             };
             ui.selectable_value(&mut self.output_source, OutputSource::Log, "Log");
             ui.separator();
-            if let Some(track_count) = core.mpv_handler.ipc(|b| b.observed.track_count) {
+            if core.mpv_handler.starting() {
+                ui.spinner();
+                ui.label("Starting mpv...");
+            }
+            if let Some(track_count) = core.mpv_handler.ipc(|b| b.observed.tracks.len()) {
                 let s = if track_count == 1 { "" } else { "s" };
-                ui.label(format!("{track_count} active track{s}"));
+                if ui
+                    .link(format!("{track_count} active track{s}"))
+                    .on_hover_text("Show tracks")
+                    .clicked()
+                {
+                    self.windows.tracks.open ^= true;
+                }
+            }
+            if core.mpv_handler.active()
+                && let Some(label) = core.loudness_label()
+            {
+                ui.label(format!("📢 {label}"));
             }
             if let Some([playlist_count, playlist_pos]) = core
                 .mpv_handler
@@ -594,6 +784,24 @@ impl Ui {
                     core.mpv_handler.ipc(Bridge::playlist_next);
                 }
             }
+            if let Some((chapters, current)) = core
+                .mpv_handler
+                .ipc(|b| (b.observed.chapters.clone(), b.observed.chapter))
+                && !chapters.is_empty()
+            {
+                ui.separator();
+                chapter_nav_ui(ui, core, modal, &chapters, current);
+            }
+            if let Some(practice) = &core.practice {
+                ui.separator();
+                ui.label(format!(
+                    "🎯 Practice: iteration {}, speed {:.2}x",
+                    practice.iteration, practice.speed
+                ));
+                if practice.count_in_until.is_some() {
+                    ui.label("(counting in)");
+                }
+            }
             if let Some(complex) = core.mpv_handler.ipc(|b| b.observed.lavfi_complex.as_str()) {
                 let mut remove = false;
                 if !complex.is_empty() {
@@ -622,7 +830,7 @@ impl Ui {
                 let out = match self.output_source {
                     OutputSource::Mpv => core.mpv_handler.mpv_output(),
                     OutputSource::Demuxer => {
-                        trim_lines(core.mpv_handler.demux_term.contents_to_string())
+                        trim_lines(core.mpv_handler.output.demux_term.contents_to_string())
                     }
                     OutputSource::Log => LOG.lock().unwrap().clone(),
                 };
@@ -630,36 +838,6 @@ impl Ui {
                     .desired_width(f32::INFINITY)
                     .font(egui::TextStyle::Monospace)
                     .show(ui);
-                if out.response.clicked()
-                    && let Some(range) = out.cursor_range
-                {
-                    let l_cur = out.galley.layout_from_cursor(range.primary);
-                    let row = l_cur.row;
-                    // The little audio "handles" are at the beginning of lines,
-                    // so let's ignore the later columns, so the user doesn't accidentally
-                    // switch tracks when clicking lines that contain --aid bits
-                    if l_cur.column > CharIndex(18) {
-                        return;
-                    }
-                    if let Some(line) = core.mpv_handler.mpv_output().lines().nth(row)
-                        && let Some(range) = line.find_token_after("--aid=")
-                    {
-                        let track_num: u64 = line[range].parse().unwrap();
-                        let [ctrl, shift] =
-                            ui.input(|inp| [inp.modifiers.ctrl, inp.modifiers.shift]);
-                        if shift {
-                            core.mpv_handler.ipc(|b| b.mix_t1_with_track(track_num));
-                        } else if ctrl {
-                            if let Some(Err(e)) =
-                                core.mpv_handler.ipc(|b| b.remove_track(track_num))
-                            {
-                                modal.error("Error removing track", e);
-                            }
-                        } else {
-                            core.mpv_handler.ipc(|b| b.switch_to_track(track_num));
-                        }
-                    }
-                }
                 // Weird hack to make PTY interaction work even if the TextEdit was clicked.
                 // Normally, the `TextEdit` is interested in keyboard events even in the
                 // "immutable" mode, which is not what we want.
@@ -675,6 +853,80 @@ impl Ui {
         self.filter_changed = false;
         core.song_change = false;
     }
+    fn bookmarks_menu_ui(
+        &mut self,
+        core: &mut Core,
+        ui: &mut egui::Ui,
+        modal: &mut ModalPopup,
+        pos: f64,
+    ) {
+        let mut jump = None;
+        let mut remove = None;
+        for (idx, bookmark) in core.bookmarks().iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button(bookmark_label(bookmark)).clicked() {
+                    jump = Some(idx);
+                }
+                if ui.button("🗑").on_hover_text("Remove").clicked() {
+                    remove = Some(idx);
+                }
+            });
+        }
+        if core.bookmarks().is_empty() {
+            ui.label("No bookmarks for this song");
+        }
+        if let Some(idx) = jump {
+            if let Some(&Bookmark { a, b: Some(b), .. }) = core.bookmarks().get(idx) {
+                self.ab_loop_a = a;
+                self.ab_loop_b = b;
+            }
+            core.jump_to_bookmark(idx)
+                .err_popup("Bookmark jump error", modal);
+        }
+        if let Some(idx) = remove {
+            core.remove_bookmark(idx);
+        }
+        ui.separator();
+        ui.add(TextEdit::singleline(&mut self.bookmark_name).hint_text("Name"));
+        let name = std::mem::take(&mut self.bookmark_name);
+        let mut new_bookmark = None;
+        ui.horizontal(|ui| {
+            if ui
+                .button("Add cue point")
+                .on_hover_text("At the current position")
+                .clicked()
+            {
+                new_bookmark = Some(Bookmark {
+                    name: name.clone(),
+                    a: pos,
+                    b: None,
+                });
+            }
+            if let Some((Some(a), Some(b))) = core.mpv_handler.ab_loop()
+                && ui.button("Add current A-B loop").clicked()
+            {
+                new_bookmark = Some(Bookmark {
+                    name: name.clone(),
+                    a,
+                    b: Some(b),
+                });
+            }
+        });
+        match new_bookmark {
+            Some(bookmark) => core.add_bookmark(bookmark),
+            None => self.bookmark_name = name,
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Previous/next keys");
+            for key in [
+                &mut core.cfg.bookmark_prev_key,
+                &mut core.cfg.bookmark_next_key,
+            ] {
+                ui.add(TextEdit::singleline(key).desired_width(24.0).char_limit(1));
+            }
+        });
+    }
     pub fn apply_colorix_theme(&mut self, theme: Option<&[[u8; 3]; 12]>, ctx: &Context) {
         if let Some(theme) = theme {
             self.colorix = Some(Colorix::global(
@@ -685,6 +937,16 @@ impl Ui {
     }
 }
 
+#[cfg(feature = "libmpv")]
+impl crate::config::BackendKind {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Process => "mpv process",
+            Self::Libmpv => "libmpv",
+        }
+    }
+}
+
 impl PlaylistBehavior {
     fn label(&self) -> &'static str {
         match self {
@@ -718,3 +980,426 @@ pub(crate) fn try_add_fallback_font(ctx: &Context, path: &Path) -> anyhow::Resul
     ));
     Ok(())
 }
+
+/// Things to mark on the seek bar
+struct Markers<'a> {
+    chapters: &'a [ChapterEntry],
+    bookmarks: &'a [Bookmark],
+}
+
+impl Markers<'_> {
+    /// Draw ticks at chapter starts and cue points, and underline bookmarked loop regions.
+    ///
+    /// `x_range` is the span of the seek bar that corresponds to the song.
+    fn draw(&self, ui: &egui::Ui, x_range: Rangef, y_range: Rangef, duration: f64) {
+        if duration <= 0.0 {
+            return;
+        }
+        let to_x = |time: f64| x_range.min + x_range.span() * (time / duration) as f32;
+        let painter = ui.painter();
+        let visuals = ui.visuals();
+        let chapter_stroke = visuals.widgets.noninteractive.fg_stroke;
+        for chapter in self.chapters.iter().filter(|ch| ch.time > 0.0) {
+            painter.vline(to_x(chapter.time), y_range, chapter_stroke);
+        }
+        let bookmark_stroke = Stroke::new(2.0, visuals.warn_fg_color);
+        for bookmark in self.bookmarks {
+            match bookmark.b {
+                Some(b) => painter.hline(
+                    to_x(bookmark.a)..=to_x(b),
+                    y_range.max - 1.0,
+                    bookmark_stroke,
+                ),
+                None => painter.vline(to_x(bookmark.a), y_range, bookmark_stroke),
+            }
+        }
+    }
+}
+
+/// Seek bar showing the waveform of the song, along with the A-B loop region and markers.
+///
+/// Returns the position to seek to, if the user clicked or dragged on it.
+fn waveform_seek_bar(
+    ui: &mut egui::Ui,
+    waveform: &Waveform,
+    info: &TimeInfo,
+    ab_loop: Option<(f64, f64)>,
+    markers: &Markers,
+) -> Option<f64> {
+    let size = egui::vec2(ui.spacing().slider_width, ui.spacing().interact_size.y);
+    let (rect, mut re) = ui.allocate_exact_size(size, Sense::click_and_drag());
+    if info.duration <= 0.0 {
+        return None;
+    }
+    let to_x = |time: f64| rect.left() + rect.width() * (time / info.duration) as f32;
+    let visuals = ui.visuals();
+    let painter = ui.painter_at(rect);
+    if let Some((a, b)) = ab_loop {
+        let loop_rect = Rect::from_x_y_ranges(to_x(a)..=to_x(b), rect.y_range());
+        painter.rect_filled(
+            loop_rect,
+            0.0,
+            visuals.selection.bg_fill.gamma_multiply(0.5),
+        );
+    }
+    let pos_x = to_x(info.pos);
+    let secs_per_px = info.duration / f64::from(rect.width());
+    for px in 0..rect.width() as u32 {
+        let from = f64::from(px) * secs_per_px;
+        let peak = waveform.peak_between(from, from + secs_per_px);
+        let half_h = (rect.height() / 2.0 * f32::from(peak) / 255.0).max(0.5);
+        let x = rect.left() + px as f32 + 0.5;
+        let color = if x <= pos_x {
+            visuals.selection.stroke.color
+        } else {
+            visuals.widgets.inactive.fg_stroke.color
+        };
+        let y = rect.center().y;
+        painter.vline(x, (y - half_h)..=(y + half_h), Stroke::new(1.0, color));
+    }
+    markers.draw(ui, rect.x_range(), rect.y_range(), info.duration);
+    painter.vline(pos_x, rect.y_range(), visuals.widgets.active.fg_stroke);
+    if let Some(ratio) = re.h_pointer_ratio() {
+        re = re
+            .on_hover_text_at_pointer(FfmpegTimeFmt(info.duration * f64::from(ratio)).to_string());
+    }
+    if re.dragged()
+        && let Some(ratio) = re.h_pointer_ratio()
+    {
+        let x = rect.left() + rect.width() * ratio;
+        painter.vline(x, rect.y_range(), visuals.widgets.hovered.fg_stroke);
+    }
+    if re.clicked() || re.drag_stopped() {
+        re.h_pointer_ratio()
+            .map(|ratio| info.duration * f64::from(ratio))
+    } else {
+        None
+    }
+}
+
+fn bookmark_label(bookmark: &Bookmark) -> String {
+    let times = match bookmark.b {
+        Some(b) => format!("{}-{}", FfmpegTimeFmt(bookmark.a), FfmpegTimeFmt(b)),
+        None => FfmpegTimeFmt(bookmark.a).to_string(),
+    };
+    let icon = if bookmark.b.is_some() { "🔁" } else { "📍" };
+    if bookmark.name.is_empty() {
+        format!("{icon} {times}")
+    } else {
+        format!("{icon} {} ({times})", bookmark.name)
+    }
+}
+
+/// Settings of practice mode, and the button to start or stop it
+fn practice_menu_ui(ui: &mut egui::Ui, core: &mut Core, modal: &mut ModalPopup) {
+    let settings = &mut core.cfg.practice;
+    egui::Grid::new("practice_grid").show(ui, |ui| {
+        ui.label("Start speed");
+        ui.add(
+            egui::DragValue::new(&mut settings.start_speed)
+                .range(0.1..=4.0)
+                .speed(0.01),
+        );
+        ui.end_row();
+        ui.label("Target speed");
+        ui.add(
+            egui::DragValue::new(&mut settings.target_speed)
+                .range(0.1..=4.0)
+                .speed(0.01),
+        );
+        ui.end_row();
+        ui.label("Step");
+        ui.add(
+            egui::DragValue::new(&mut settings.step)
+                .range(0.0..=1.0)
+                .speed(0.01),
+        )
+        .on_hover_text("How much faster each repeat of the loop gets");
+        ui.end_row();
+        ui.label("Count-in");
+        ui.add(
+            egui::DragValue::new(&mut settings.count_in)
+                .range(0.0..=10.0)
+                .speed(0.1)
+                .suffix(" s"),
+        )
+        .on_hover_text("Silence before each repeat");
+        ui.end_row();
+    });
+    if core.practice.is_some() {
+        if ui.button("Stop practice").clicked() {
+            core.stop_practice().err_popup("Practice mode error", modal);
+        }
+    } else if core
+        .mpv_handler
+        .ab_loop()
+        .is_none_or(|(a, b)| a.is_none() || b.is_none())
+    {
+        ui.label("Set an A-B loop to practice");
+    } else if ui.button("Start practice").clicked() {
+        core.start_practice()
+            .err_popup("Practice mode error", modal);
+    }
+}
+
+/// Chapter dropdown with previous/next chapter buttons
+fn chapter_nav_ui(
+    ui: &mut egui::Ui,
+    core: &mut Core,
+    modal: &mut ModalPopup,
+    chapters: &[ChapterEntry],
+    current: Option<usize>,
+) {
+    if ui
+        .button(ICO_PREV)
+        .on_hover_text("Previous chapter")
+        .clicked()
+    {
+        core.mpv_handler
+            .ipc(|b| b.add_chapter(-1))
+            .err_popup("Chapter change error", modal);
+    }
+    let selected_text = match current.and_then(|idx| Some((idx, chapters.get(idx)?))) {
+        Some((idx, chapter)) => chapter_label(idx, chapter),
+        None => "-".into(),
+    };
+    let mut new_chapter = None;
+    ComboBox::new("chapter_cb", "")
+        .width(200.0)
+        .selected_text(selected_text)
+        .truncate()
+        .show_ui(ui, |ui| {
+            for (idx, chapter) in chapters.iter().enumerate() {
+                if ui
+                    .selectable_label(current == Some(idx), chapter_label(idx, chapter))
+                    .clicked()
+                {
+                    new_chapter = Some(idx);
+                }
+            }
+        })
+        .response
+        .on_hover_text("Chapter");
+    if let Some(idx) = new_chapter {
+        core.mpv_handler
+            .ipc(|b| b.set_chapter(idx))
+            .err_popup("Chapter change error", modal);
+    }
+    if ui.button(ICO_NEXT).on_hover_text("Next chapter").clicked() {
+        core.mpv_handler
+            .ipc(|b| b.add_chapter(1))
+            .err_popup("Chapter change error", modal);
+    }
+}
+
+fn chapter_label(idx: usize, chapter: &ChapterEntry) -> String {
+    let num = idx + 1;
+    let time = FfmpegTimeFmt(chapter.time);
+    if chapter.title.is_empty() {
+        format!("{num}. Chapter {num} ({time})")
+    } else {
+        format!("{num}. {} ({time})", chapter.title)
+    }
+}
+
+/// Lowest level the level meter shows, in dBFS
+const METER_MIN_DB: f64 = -60.0;
+
+/// All the sleep timer controls
+fn sleep_timer_menu_ui(ui: &mut egui::Ui, core: &mut Core, modal: &mut ModalPopup) {
+    let settings = &mut core.cfg.sleep_timer;
+    let mut new_timer = None;
+    ui.horizontal(|ui| {
+        if ui.button("Stop after").clicked() {
+            new_timer = Some(SleepTimer::after_minutes(settings.minutes));
+        }
+        ui.add(
+            egui::DragValue::new(&mut settings.minutes)
+                .range(1..=600)
+                .suffix(" min"),
+        );
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Stop after").clicked() {
+            new_timer = Some(SleepTimer::new(SleepTimerKind::AfterSongs(settings.songs)));
+        }
+        ui.add(
+            egui::DragValue::new(&mut settings.songs)
+                .range(1..=100)
+                .suffix(" songs"),
+        );
+    });
+    if ui.button("Stop after this song").clicked() {
+        new_timer = Some(SleepTimer::new(SleepTimerKind::AfterSongs(1)));
+    }
+    ui.checkbox(&mut settings.fade_out, "Fade out over the last minute");
+    if let Some(timer) = new_timer {
+        core.start_sleep_timer(timer)
+            .err_popup("Sleep timer error", modal);
+    }
+    if let Some(timer) = &core.sleep_timer {
+        ui.separator();
+        ui.label(timer.label());
+        if ui.button("Cancel").clicked() {
+            core.cancel_sleep_timer()
+                .err_popup("Sleep timer error", modal);
+        }
+    }
+}
+
+/// Compact sleep timer controls, for the tray popup
+pub(crate) fn sleep_timer_row(ui: &mut egui::Ui, core: &mut Core, modal: &mut ModalPopup) {
+    let result = match &core.sleep_timer {
+        Some(timer) => {
+            ui.label(timer.label());
+            if ui.button("✖").on_hover_text("Cancel").clicked() {
+                core.cancel_sleep_timer()
+            } else {
+                Ok(())
+            }
+        }
+        None => {
+            let minutes = core.cfg.sleep_timer.minutes;
+            if ui
+                .button(format!("💤 {minutes} min"))
+                .on_hover_text("Stop after this many minutes")
+                .clicked()
+            {
+                core.start_sleep_timer(SleepTimer::after_minutes(minutes))
+            } else if ui
+                .button("💤 This song")
+                .on_hover_text("Stop after this song")
+                .clicked()
+            {
+                core.start_sleep_timer(SleepTimer::new(SleepTimerKind::AfterSongs(1)))
+            } else {
+                Ok(())
+            }
+        }
+    };
+    result.err_popup("Sleep timer error", modal);
+}
+
+/// Cover art of the song, `size` pixels wide and high
+pub(crate) fn cover_art_image(ui: &mut egui::Ui, art: &CoverArt, size: f32) {
+    // Each window has its own context, so the texture is kept in the context's memory
+    let id = egui::Id::new("cover_art_texture");
+    let cached = ui
+        .ctx()
+        .data(|data| data.get_temp::<(PathBuf, TextureHandle)>(id))
+        .filter(|(path, _)| *path == art.path);
+    let texture = match cached {
+        Some((_, texture)) => texture,
+        None => {
+            let image = egui::ColorImage::from_rgba_unmultiplied(
+                [cover_art::SIZE, cover_art::SIZE],
+                &art.rgba,
+            );
+            let texture = (ui.ctx()).load_texture("cover_art", image, TextureOptions::LINEAR);
+            ui.ctx()
+                .data_mut(|data| data.insert_temp(id, (art.path.clone(), texture.clone())));
+            texture
+        }
+    };
+    ui.image((texture.id(), egui::vec2(size, size)))
+        .on_hover_text(art.path.display().to_string());
+}
+
+/// Meter of the audio levels measured by mpv, with a bar for each channel.
+///
+/// Shows empty bars if there is nothing to measure.
+pub(crate) fn level_meter(ui: &mut egui::Ui, core: &mut Core, width: f32) {
+    const BAR_H: f32 = 5.0;
+    const GAP: f32 = 2.0;
+    let levels = core
+        .mpv_handler
+        .ipc(|b| b.observed.levels.clone())
+        .unwrap_or_default();
+    let n_bars = levels.len().max(2);
+    let height = n_bars as f32 * (BAR_H + GAP) - GAP;
+    let (rect, re) = ui.allocate_exact_size(egui::vec2(width, height), Sense::hover());
+    let visuals = ui.visuals();
+    let painter = ui.painter_at(rect);
+    let to_x = |db: f64| {
+        let ratio = ((db - METER_MIN_DB) / -METER_MIN_DB).clamp(0.0, 1.0);
+        rect.left() + rect.width() * ratio as f32
+    };
+    for i in 0..n_bars {
+        let top = rect.top() + i as f32 * (BAR_H + GAP);
+        let y_range = Rangef::new(top, top + BAR_H);
+        painter.rect_filled(
+            Rect::from_x_y_ranges(rect.x_range(), y_range),
+            0.0,
+            visuals.extreme_bg_color,
+        );
+        let Some(level) = levels.get(i) else {
+            continue;
+        };
+        let fill = Rect::from_x_y_ranges(rect.left()..=to_x(level.rms), y_range);
+        painter.rect_filled(fill, 0.0, visuals.selection.bg_fill);
+        // Warn about (almost) clipping
+        let peak_color = if level.peak > -1.0 {
+            visuals.error_fg_color
+        } else {
+            visuals.strong_text_color()
+        };
+        painter.vline(to_x(level.peak), y_range, Stroke::new(2.0, peak_color));
+    }
+    if levels.is_empty() {
+        re.on_hover_text("No level data");
+    } else {
+        re.on_hover_ui(|ui| {
+            for (i, level) in levels.iter().enumerate() {
+                ui.label(format!(
+                    "Channel {}: {:.1} dB (peak {:.1} dB)",
+                    i + 1,
+                    level.rms,
+                    level.peak
+                ));
+            }
+        });
+    }
+}
+
+/// Combo box for picking the audio output device
+pub(crate) fn audio_device_combo(
+    ui: &mut egui::Ui,
+    core: &mut Core,
+    modal: &mut ModalPopup,
+    width: f32,
+) {
+    let current = core.cfg.audio_device.as_deref().unwrap_or("auto");
+    let selected_text = match core.audio_devices.iter().find(|dev| dev.name == current) {
+        Some(dev) => dev.description.as_str(),
+        None if current == "auto" => "Auto",
+        None => current,
+    };
+    let mut new_device = None;
+    ComboBox::new("audio_device_cb", "")
+        .width(width)
+        .selected_text(selected_text)
+        .truncate()
+        .show_ui(ui, |ui| {
+            if core.audio_devices.is_empty() {
+                // We only know the devices once mpv has told us about them
+                if ui.selectable_label(current == "auto", "Auto").clicked() {
+                    new_device = Some(None);
+                }
+            }
+            for dev in &core.audio_devices {
+                if ui
+                    .selectable_label(current == dev.name, &dev.description)
+                    .on_hover_text(&dev.name)
+                    .clicked()
+                {
+                    new_device = Some((dev.name != "auto").then(|| dev.name.clone()));
+                }
+            }
+        })
+        .response
+        .on_hover_text("Audio output device");
+    if let Some(device) = new_device {
+        core.set_audio_device(device)
+            .err_popup("Audio device error", modal);
+    }
+}
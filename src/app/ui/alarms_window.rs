@@ -0,0 +1,102 @@
+use {
+    crate::{app::Core, config::Alarm},
+    chrono::{NaiveTime, Timelike as _},
+    egui_sf2g::egui::{self, Context, TextEdit, Ui, Window},
+    std::path::PathBuf,
+};
+
+#[derive(Default)]
+pub struct AlarmsWindow {
+    pub open: bool,
+}
+
+impl AlarmsWindow {
+    pub(super) fn update(&mut self, core: &mut Core, ctx: &Context) {
+        Window::new("⏰ Alarms")
+            .open(&mut self.open)
+            .show(ctx, |ui| window_ui(core, ui));
+    }
+}
+
+fn window_ui(core: &mut Core, ui: &mut Ui) {
+    let current_song = (core.cfg.music_folder.as_ref())
+        .zip(core.played_path.as_ref())
+        .map(|(folder, path)| folder.join(path));
+    let mut remove = None;
+    egui::Grid::new("alarms_grid").striped(true).show(ui, |ui| {
+        ui.label("");
+        ui.label("Time");
+        ui.label("Play");
+        ui.label("Volume");
+        ui.label("Fade-in");
+        ui.label("");
+        ui.end_row();
+        for (idx, alarm) in core.cfg.alarms.iter_mut().enumerate() {
+            ui.checkbox(&mut alarm.enabled, "").on_hover_text("Enabled");
+            time_ui(ui, &mut alarm.time);
+            ui.horizontal(|ui| {
+                let mut path = alarm.path.to_string_lossy().into_owned();
+                if ui
+                    .add(TextEdit::singleline(&mut path).hint_text("Song, playlist or folder"))
+                    .changed()
+                {
+                    alarm.path = PathBuf::from(path);
+                }
+                if let Some(song) = &current_song
+                    && ui
+                        .button("♪")
+                        .on_hover_text("Use the current song")
+                        .clicked()
+                {
+                    alarm.path.clone_from(song);
+                }
+            });
+            ui.add(egui::DragValue::new(&mut alarm.volume).range(0..=150));
+            ui.add(
+                egui::DragValue::new(&mut alarm.fade_in)
+                    .range(0.0..=600.0)
+                    .suffix(" s"),
+            );
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut alarm.once, "once")
+                    .on_hover_text("Disable the alarm after it went off");
+                if ui.button("🗑").on_hover_text("Remove").clicked() {
+                    remove = Some(idx);
+                }
+            });
+            ui.end_row();
+        }
+    });
+    if let Some(idx) = remove {
+        core.cfg.alarms.remove(idx);
+    }
+    if ui.button("➕ Add alarm").clicked() {
+        core.cfg.alarms.push(Alarm {
+            path: current_song
+                .or_else(|| core.cfg.music_folder.clone())
+                .unwrap_or_default(),
+            ..Default::default()
+        });
+    }
+    ui.label("Alarms only go off while mpvfrog is running");
+}
+
+fn time_ui(ui: &mut Ui, time: &mut NaiveTime) {
+    let (mut hour, mut min) = (time.hour(), time.minute());
+    ui.horizontal(|ui| {
+        let mut changed = ui
+            .add(egui::DragValue::new(&mut hour).range(0..=23))
+            .changed();
+        ui.label(":");
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut min)
+                    .range(0..=59)
+                    .custom_formatter(|n, _| format!("{n:02}")),
+            )
+            .changed();
+        if changed && let Some(new) = NaiveTime::from_hms_opt(hour, min, 0) {
+            *time = new;
+        }
+    });
+}
@@ -1,7 +1,10 @@
 use {
+    super::equalizer_window::eq_preset_combo,
     crate::{
         app::Core,
+        audio_filter::EqPreset,
         config::{Command, CustomDemuxerEntry, HasExtsPredicate, Predicate, PredicateKind},
+        util::str_ext::{join_args, split_args},
     },
     egui_sf2g::egui::{self, Color32, ComboBox, Context, RichText, ScrollArea, Ui, Window},
 };
@@ -127,11 +130,16 @@ impl CustomDemuxersWindow {
         });
         ui.separator();
         if let Some(custom) = core.cfg.custom_demuxers.get_mut(self.selected_idx) {
-            self.selected_demuxer_ui(ui, custom);
+            self.selected_demuxer_ui(ui, custom, &core.cfg.eq_presets);
         }
     }
 
-    fn selected_demuxer_ui(&mut self, ui: &mut Ui, custom: &mut CustomDemuxerEntry) {
+    fn selected_demuxer_ui(
+        &mut self,
+        ui: &mut Ui,
+        custom: &mut CustomDemuxerEntry,
+        eq_presets: &[EqPreset],
+    ) {
         let idx = self.selected_idx;
         ui.horizontal(|ui| {
             ui.label("Name");
@@ -216,11 +224,7 @@ impl CustomDemuxersWindow {
                             )
                             .lost_focus()
                         {
-                            custom.extra_mpv_args = self
-                                .edit_buffer
-                                .split_whitespace()
-                                .map(String::from)
-                                .collect();
+                            custom.extra_mpv_args = split_args(&self.edit_buffer);
                             self.edit_buffer.clear();
                             self.edit_target = None;
                         }
@@ -228,13 +232,13 @@ impl CustomDemuxersWindow {
                     _ => {
                         if ui
                             .add(
-                                egui::TextEdit::multiline(&mut custom.extra_mpv_args.join(" "))
+                                egui::TextEdit::multiline(&mut join_args(&custom.extra_mpv_args))
                                     .desired_rows(3)
                                     .desired_width(f32::INFINITY),
                             )
                             .gained_focus()
                         {
-                            self.edit_buffer = custom.extra_mpv_args.join(" ");
+                            self.edit_buffer = join_args(&custom.extra_mpv_args);
                             self.edit_target = Some(EditTarget {
                                 index: idx,
                                 which: EditTargetWhich::MpvArgs,
@@ -242,6 +246,18 @@ impl CustomDemuxersWindow {
                         }
                     }
                 }
+                ui.horizontal(|ui| {
+                    ui.label("Equalizer preset");
+                    eq_preset_combo(
+                        ui,
+                        ("demux_eq_cb", idx),
+                        eq_presets,
+                        &mut custom.eq_preset,
+                        Some("Default"),
+                    );
+                })
+                .response
+                .on_hover_text("Overrides the preset selected in the equalizer window");
             }
             CustomDemuxTab::Predicates => {
                 ui.horizontal(|ui| {
@@ -0,0 +1,245 @@
+use {
+    crate::{
+        app::Core,
+        audio_filter::{EQ_BAND_FREQS, EqPreset},
+        config::FolderEqPreset,
+        util::result_ext::LogErrExt as _,
+    },
+    egui_sf2g::egui::{self, Color32, ComboBox, Context, RichText, Ui, Window},
+    std::hash::Hash,
+};
+
+#[derive(Default)]
+pub struct EqualizerWindow {
+    pub open: bool,
+    /// Index of the preset being edited
+    selected_idx: usize,
+    /// Index of the preset being renamed. The new name is applied when done editing.
+    name_edit: Option<usize>,
+    name_buffer: String,
+    error_label: String,
+}
+
+/// Maximum boost/cut of a band, in dB
+const MAX_GAIN: f64 = 12.0;
+
+impl EqualizerWindow {
+    pub(super) fn update(&mut self, core: &mut Core, ctx: &Context) {
+        let mut open = self.open;
+        Window::new("🎚 Equalizer")
+            .open(&mut open)
+            .show(ctx, |ui| self.window_ui(core, ui));
+        self.open = open;
+    }
+    fn window_ui(&mut self, core: &mut Core, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Preset");
+            let mut selected = core.cfg.eq_preset.clone();
+            if eq_preset_combo(
+                ui,
+                "eq_active_cb",
+                &core.cfg.eq_presets,
+                &mut selected,
+                Some("Off"),
+            ) {
+                core.set_eq_preset(selected)
+                    .log_err("Failed to set equalizer preset");
+            }
+        });
+        if core.mpv_handler.active() && core.eq_preset != core.cfg.eq_preset {
+            ui.label(format!(
+                "The current song uses the preset of its folder or demuxer ({})",
+                core.eq_preset.as_deref().unwrap_or("Off")
+            ));
+        }
+        ui.separator();
+        ui.horizontal_wrapped(|ui| {
+            for (idx, preset) in core.cfg.eq_presets.iter().enumerate() {
+                if ui
+                    .selectable_label(self.selected_idx == idx, &preset.name)
+                    .clicked()
+                {
+                    self.selected_idx = idx;
+                }
+            }
+            if ui.button("➕").on_hover_text("New preset").clicked() {
+                let name = core.cfg.unused_eq_preset_name("Preset");
+                core.cfg.eq_presets.push(EqPreset::new(name));
+                self.selected_idx = core.cfg.eq_presets.len() - 1;
+            }
+        });
+        if let Some(preset) = core.cfg.eq_presets.get(self.selected_idx) {
+            let name = preset.name.clone();
+            let mut rename = None;
+            let mut changed = false;
+            let mut op = None;
+            if self.name_edit != Some(self.selected_idx) {
+                self.name_edit = None;
+            }
+            ui.horizontal(|ui| {
+                if self.name_edit.is_some() {
+                    if ui.text_edit_singleline(&mut self.name_buffer).lost_focus() {
+                        rename = Some(std::mem::take(&mut self.name_buffer));
+                        self.name_edit = None;
+                    }
+                } else if ui.text_edit_singleline(&mut name.clone()).gained_focus() {
+                    self.name_buffer.clone_from(&name);
+                    self.name_edit = Some(self.selected_idx);
+                    self.error_label.clear();
+                }
+                if ui.button("🗐").on_hover_text("Clone").clicked() {
+                    op = Some(Op::Clone);
+                }
+                if ui.button("🗑").on_hover_text("Delete").clicked() {
+                    op = Some(Op::Remove);
+                }
+            });
+            if let Some(new_name) = rename
+                && new_name != name
+            {
+                match core.cfg.rename_eq_preset(&name, &new_name) {
+                    Ok(()) => {
+                        if core.eq_preset.as_deref() == Some(&name) {
+                            core.eq_preset = Some(new_name);
+                        }
+                    }
+                    Err(e) => self.error_label = e.to_string(),
+                }
+            }
+            if !self.error_label.is_empty() {
+                ui.label(RichText::new(&self.error_label).color(Color32::RED));
+            }
+            let preset = &mut core.cfg.eq_presets[self.selected_idx];
+            let in_effect = core.eq_preset.as_ref() == Some(&preset.name);
+            ui.horizontal(|ui| {
+                for (freq, gain) in EQ_BAND_FREQS.iter().zip(&mut preset.bands) {
+                    ui.vertical(|ui| {
+                        changed |= ui
+                            .add(egui::Slider::new(gain, -MAX_GAIN..=MAX_GAIN).vertical())
+                            .changed();
+                        ui.label(freq_label(*freq));
+                    });
+                }
+            });
+            changed |= ui
+                .add(egui::Slider::new(&mut preset.bass, -MAX_GAIN..=MAX_GAIN).text("Bass"))
+                .changed();
+            changed |= ui
+                .add(egui::Slider::new(&mut preset.treble, -MAX_GAIN..=MAX_GAIN).text("Treble"))
+                .changed();
+            if ui.button("Flat").clicked() {
+                *preset = EqPreset::new(preset.name.clone());
+                changed = true;
+            }
+            match op {
+                Some(Op::Clone) => {
+                    let mut clone = preset.clone();
+                    clone.name = core
+                        .cfg
+                        .unused_eq_preset_name(&format!("{} (copy)", clone.name));
+                    core.cfg.eq_presets.push(clone);
+                    self.selected_idx = core.cfg.eq_presets.len() - 1;
+                }
+                Some(Op::Remove) => {
+                    core.cfg.remove_eq_preset(&name);
+                    if core.eq_preset.as_deref() == Some(&name) {
+                        core.eq_preset = None;
+                    }
+                    self.selected_idx = self.selected_idx.saturating_sub(1);
+                    changed = true;
+                }
+                None => {}
+            }
+            if changed && in_effect {
+                core.apply_audio_filters()
+                    .log_err("Failed to apply equalizer");
+            }
+        }
+        ui.separator();
+        self.folder_presets_ui(core, ui);
+    }
+    fn folder_presets_ui(&mut self, core: &mut Core, ui: &mut Ui) {
+        ui.label("Folder presets").on_hover_text(
+            "Songs in these folders use the given preset. Applies to the next song.",
+        );
+        let mut remove = None;
+        for (idx, en) in core.cfg.folder_eq_presets.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(en.folder.display().to_string());
+                let mut selected = Some(en.preset.clone());
+                if eq_preset_combo(
+                    ui,
+                    ("eq_folder_cb", idx),
+                    &core.cfg.eq_presets,
+                    &mut selected,
+                    None,
+                ) && let Some(name) = selected
+                {
+                    en.preset = name;
+                }
+                if ui.button("🗑").on_hover_text("Remove").clicked() {
+                    remove = Some(idx);
+                }
+            });
+        }
+        if let Some(idx) = remove {
+            core.cfg.folder_eq_presets.remove(idx);
+        }
+        let folder = core
+            .played_path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .map(ToOwned::to_owned);
+        let can_add = folder.is_some() && !core.cfg.eq_presets.is_empty();
+        if ui
+            .add_enabled(can_add, egui::Button::new("➕ Add folder of current song"))
+            .clicked()
+            && let Some(folder) = folder
+        {
+            core.cfg.folder_eq_presets.push(FolderEqPreset {
+                folder,
+                preset: core.cfg.eq_presets[0].name.clone(),
+            });
+        }
+    }
+}
+
+enum Op {
+    Clone,
+    Remove,
+}
+
+fn freq_label(freq: u32) -> String {
+    if freq >= 1000 {
+        format!("{}k", freq / 1000)
+    } else {
+        freq.to_string()
+    }
+}
+
+/// Combo box for picking an equalizer preset by name.
+///
+/// If `none_label` is given, "no preset" can also be picked. Returns whether the selection changed.
+pub(super) fn eq_preset_combo(
+    ui: &mut Ui,
+    id_salt: impl Hash,
+    presets: &[EqPreset],
+    selected: &mut Option<String>,
+    none_label: Option<&str>,
+) -> bool {
+    let mut changed = false;
+    let selected_text = selected.as_deref().or(none_label).unwrap_or_default();
+    ComboBox::from_id_salt(id_salt)
+        .selected_text(selected_text)
+        .show_ui(ui, |ui| {
+            if let Some(label) = none_label {
+                changed |= ui.selectable_value(selected, None, label).changed();
+            }
+            for preset in presets {
+                changed |= ui
+                    .selectable_value(selected, Some(preset.name.clone()), &preset.name)
+                    .changed();
+            }
+        });
+    changed
+}
@@ -0,0 +1,94 @@
+use {
+    crate::{
+        app::Core,
+        audio_filter::{Normalization, ReplayGain},
+        util::result_ext::LogErrExt as _,
+    },
+    egui_sf2g::egui::{self, ComboBox, Context, Ui, Window},
+};
+
+#[derive(Default)]
+pub struct LoudnessWindow {
+    pub open: bool,
+}
+
+impl ReplayGain {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Track => "Track",
+            Self::Album => "Album",
+        }
+    }
+}
+
+impl Normalization {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Loudnorm => "loudnorm (EBU R128)",
+            Self::Dynaudnorm => "dynaudnorm (dynamic)",
+        }
+    }
+}
+
+impl LoudnessWindow {
+    pub(super) fn update(&mut self, core: &mut Core, ctx: &Context) {
+        Window::new("📢 Loudness")
+            .open(&mut self.open)
+            .show(ctx, |ui| window_ui(core, ui));
+    }
+}
+
+fn window_ui(core: &mut Core, ui: &mut Ui) {
+    let cfg = &mut core.cfg;
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("ReplayGain");
+        ComboBox::new("replaygain_cb", "")
+            .selected_text(cfg.replaygain.label())
+            .show_ui(ui, |ui| {
+                for mode in [ReplayGain::Off, ReplayGain::Track, ReplayGain::Album] {
+                    changed |= ui
+                        .selectable_value(&mut cfg.replaygain, mode, mode.label())
+                        .changed();
+                }
+            });
+    });
+    ui.add_enabled_ui(cfg.replaygain != ReplayGain::Off, |ui| {
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut cfg.replaygain_preamp, -15.0..=15.0)
+                    .text("Preamp (dB)")
+                    .step_by(0.5),
+            )
+            .changed();
+        changed |= ui
+            .checkbox(&mut cfg.replaygain_clip_prevention, "Prevent clipping")
+            .on_hover_text("Lower the gain if it would make the song clip")
+            .changed();
+    });
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label("Normalization");
+        ComboBox::new("normalization_cb", "")
+            .selected_text(cfg.normalization.label())
+            .show_ui(ui, |ui| {
+                for mode in [
+                    Normalization::Off,
+                    Normalization::Loudnorm,
+                    Normalization::Dynaudnorm,
+                ] {
+                    changed |= ui
+                        .selectable_value(&mut cfg.normalization, mode, mode.label())
+                        .changed();
+                }
+            })
+            .response
+            .on_hover_text("Normalize with a filter. Useful for files without ReplayGain tags.");
+    });
+    if changed {
+        core.apply_loudness_settings()
+            .log_err("Failed to apply loudness settings");
+    }
+}
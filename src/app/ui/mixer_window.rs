@@ -0,0 +1,99 @@
+use {
+    crate::{
+        app::Core,
+        ipc::{TrackEntry, TrackKind},
+        util::result_ext::LogErrExt as _,
+    },
+    egui_sf2g::egui::{self, Context, Response, Ui, Window},
+};
+
+#[derive(Default)]
+pub struct MixerWindow {
+    pub open: bool,
+}
+
+/// Range of the gain sliders, in dB
+const GAIN_RANGE: std::ops::RangeInclusive<f64> = -30.0..=12.0;
+/// Range of the offset of a track, in seconds
+const OFFSET_RANGE: std::ops::RangeInclusive<f64> = -30.0..=30.0;
+
+impl MixerWindow {
+    pub(super) fn update(&mut self, core: &mut Core, ctx: &Context) {
+        Window::new("🎛 Mixer")
+            .open(&mut self.open)
+            .show(ctx, |ui| window_ui(core, ui));
+    }
+}
+
+fn window_ui(core: &mut Core, ui: &mut Ui) {
+    let Some(tracks) = core.mpv_handler.ipc(|b| b.observed.tracks.clone()) else {
+        ui.label("Nothing is playing");
+        return;
+    };
+    core.sync_mixer();
+    let mut apply = false;
+    egui::Grid::new("mixer_grid").striped(true).show(ui, |ui| {
+        ui.label("Track");
+        ui.label("Gain (dB)");
+        ui.label("");
+        ui.label("Offset (s)");
+        ui.end_row();
+        for mix in &mut core.mixer {
+            let name = (tracks.iter())
+                .find(|track| track.id == mix.id && track.kind == TrackKind::Audio)
+                .map_or_else(|| format!("#{}", mix.id), track_name);
+            ui.label(name);
+            apply |= applies(&ui.add(egui::Slider::new(&mut mix.gain, GAIN_RANGE)));
+            ui.horizontal(|ui| {
+                apply |= ui
+                    .toggle_value(&mut mix.mute, "🔇")
+                    .on_hover_text("Mute")
+                    .changed();
+                apply |= ui
+                    .toggle_value(&mut mix.solo, "S")
+                    .on_hover_text("Solo")
+                    .changed();
+            });
+            let re = ui
+                .add(
+                    egui::DragValue::new(&mut mix.offset)
+                        .range(OFFSET_RANGE)
+                        .speed(0.01)
+                        .suffix(" s"),
+                )
+                .on_hover_text("Delay the track. Negative values skip its start instead.");
+            apply |= applies(&re);
+            ui.end_row();
+        }
+    });
+    ui.separator();
+    ui.horizontal(|ui| {
+        apply |= ui.button("Mix").clicked();
+        if ui
+            .button("Unmix")
+            .on_hover_text("Only play the first audio track")
+            .clicked()
+        {
+            (core.mpv_handler.ipc(|b| b.switch_to_track(1)))
+                .unwrap_or(Ok(()))
+                .log_err("Failed to unmix");
+        }
+    });
+    if apply {
+        core.apply_mixer().log_err("Failed to apply mixer");
+    }
+}
+
+/// Whether an edit should be applied. Applying restarts audio, so not while dragging.
+fn applies(re: &Response) -> bool {
+    re.drag_stopped() || (re.changed() && !re.dragged())
+}
+
+fn track_name(track: &TrackEntry) -> String {
+    let mut name = format!("#{}", track.id);
+    for part in [&track.title, &track.lang].into_iter().flatten() {
+        name.push(' ');
+        name.push_str(part);
+    }
+    name
+}
@@ -0,0 +1,159 @@
+use {
+    crate::{
+        app::Core,
+        config::{ExtArgProfile, HasExtsPredicate},
+        util::str_ext::{join_args, split_args},
+    },
+    egui_sf2g::egui::{self, Color32, Context, RichText, ScrollArea, Ui, Window},
+    std::sync::mpsc::{Receiver, TryRecvError},
+};
+
+#[derive(Default)]
+pub struct MpvSettingsWindow {
+    pub open: bool,
+    edit_buffer: String,
+    edit_target: Option<EditTarget>,
+    /// Output of `mpv --version`, or the reason it failed
+    version_result: Option<Result<String, String>>,
+    /// Running `mpv --version` on a background thread
+    version_recv: Option<Receiver<Result<String, String>>>,
+}
+
+#[derive(PartialEq)]
+enum EditTarget {
+    GlobalArgs,
+    ProfileArgs(usize),
+}
+
+impl MpvSettingsWindow {
+    pub(super) fn update(&mut self, core: &mut Core, ctx: &Context) {
+        if let Some(recv) = &self.version_recv {
+            match recv.try_recv() {
+                Ok(result) => {
+                    self.version_result = Some(result);
+                    self.version_recv = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.version_recv = None,
+            }
+        }
+        let mut open = self.open;
+        Window::new("⚙ Mpv settings")
+            .open(&mut open)
+            .show(ctx, |ui| self.window_ui(core, ui));
+        self.open = open;
+    }
+    fn window_ui(&mut self, core: &mut Core, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Mpv executable");
+            if ui.text_edit_singleline(&mut core.cfg.mpv_path).changed() {
+                self.version_result = None;
+            }
+            if ui
+                .add_enabled(self.version_recv.is_none(), egui::Button::new("Check"))
+                .on_hover_text("Run `mpv --version`")
+                .clicked()
+            {
+                self.version_recv = Some(spawn_version_check(core.cfg.mpv_path.clone()));
+            }
+        });
+        if self.version_recv.is_some() {
+            ui.spinner();
+        }
+        match &self.version_result {
+            Some(Ok(version)) => {
+                ui.label(RichText::new(version).color(Color32::GREEN));
+            }
+            Some(Err(e)) => {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+            None => {}
+        }
+        ui.separator();
+        ui.label("Extra mpv args")
+            .on_hover_text("Passed to mpv for every song");
+        self.args_edit_ui(ui, EditTarget::GlobalArgs, &mut core.cfg.extra_mpv_args);
+        ui.label("Example: --audio-device=alsa/default --config-dir='/home/me/My Config'");
+        ui.separator();
+        ui.label("Per-extension args")
+            .on_hover_text("Passed to mpv for songs with matching file extensions");
+        let mut remove = None;
+        ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            for (idx, profile) in core.cfg.ext_arg_profiles.iter_mut().enumerate() {
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut profile.exts.ext_list)
+                                .hint_text("Space separated list of extensions (e.g. `flac wav`)"),
+                        );
+                        ui.checkbox(&mut profile.exts.case_sensitive, "Case sensitive");
+                        if ui.button("🗑").on_hover_text("Delete").clicked() {
+                            remove = Some(idx);
+                        }
+                    });
+                    self.args_edit_ui(ui, EditTarget::ProfileArgs(idx), &mut profile.args);
+                });
+            }
+        });
+        if let Some(idx) = remove {
+            core.cfg.ext_arg_profiles.remove(idx);
+            self.edit_target = None;
+        }
+        if ui.button("➕ Add").clicked() {
+            core.cfg.ext_arg_profiles.push(ExtArgProfile {
+                exts: HasExtsPredicate::default(),
+                args: Vec::new(),
+            });
+        }
+    }
+    /// Arguments are edited as a command line, and only applied when done editing
+    fn args_edit_ui(&mut self, ui: &mut Ui, target: EditTarget, args: &mut Vec<String>) {
+        if self.edit_target.as_ref() == Some(&target) {
+            if ui
+                .add(
+                    egui::TextEdit::multiline(&mut self.edit_buffer)
+                        .desired_rows(2)
+                        .desired_width(f32::INFINITY),
+                )
+                .lost_focus()
+            {
+                *args = split_args(&self.edit_buffer);
+                self.edit_buffer.clear();
+                self.edit_target = None;
+            }
+        } else if ui
+            .add(
+                egui::TextEdit::multiline(&mut join_args(args))
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            )
+            .gained_focus()
+        {
+            self.edit_buffer = join_args(args);
+            self.edit_target = Some(target);
+        }
+    }
+}
+
+/// Run `mpv --version` on a background thread, so a hanging executable can't block the ui
+fn spawn_version_check(mpv_path: String) -> Receiver<Result<String, String>> {
+    let (send, recv) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let result = match std::process::Command::new(&mpv_path)
+            .arg("--version")
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+            }
+            Ok(output) => Err(format!(
+                "`{mpv_path} --version` exited with {}\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => Err(format!("Failed to run `{mpv_path}`: {e}")),
+        };
+        let _ = send.send(result);
+    });
+    recv
+}
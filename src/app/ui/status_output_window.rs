@@ -0,0 +1,40 @@
+use {
+    crate::app::Core,
+    egui_sf2g::egui::{Context, TextEdit, Ui, Window},
+    std::path::PathBuf,
+};
+
+#[derive(Default)]
+pub struct StatusOutputWindow {
+    pub open: bool,
+}
+
+impl StatusOutputWindow {
+    pub(super) fn update(&mut self, core: &mut Core, ctx: &Context) {
+        Window::new("📝 Status output")
+            .open(&mut self.open)
+            .show(ctx, |ui| window_ui(core, ui));
+    }
+}
+
+fn window_ui(core: &mut Core, ui: &mut Ui) {
+    ui.label("Tray tooltip");
+    ui.add(TextEdit::multiline(&mut core.cfg.tooltip_template).desired_rows(2));
+    ui.separator();
+    ui.label("Status file");
+    let mut path = (core.cfg.status_file.as_ref())
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if ui
+        .add(TextEdit::singleline(&mut path).hint_text("Path of a file or FIFO"))
+        .changed()
+    {
+        core.cfg.status_file = (!path.is_empty()).then(|| PathBuf::from(path));
+    }
+    ui.add(TextEdit::singleline(&mut core.cfg.status_template));
+    ui.separator();
+    ui.label(
+        "Variables: {file} {path} {artist} {album} {title} {state} {pos} {dur} {volume} \
+         {speed} {mpv}",
+    );
+}
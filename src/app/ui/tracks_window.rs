@@ -0,0 +1,87 @@
+use {
+    crate::{
+        app::Core,
+        ipc::{TrackEntry, TrackKind},
+        util::result_ext::LogErrExt as _,
+    },
+    egui_sf2g::egui::{self, Context, Ui, Window},
+};
+
+#[derive(Default)]
+pub struct TracksWindow {
+    pub open: bool,
+}
+
+impl TrackKind {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Audio => "Audio",
+            Self::Video => "Video",
+            Self::Sub => "Subtitles",
+        }
+    }
+}
+
+impl TracksWindow {
+    pub(super) fn update(&mut self, core: &mut Core, ctx: &Context) {
+        Window::new("🎞 Tracks")
+            .open(&mut self.open)
+            .show(ctx, |ui| window_ui(core, ui));
+    }
+}
+
+fn window_ui(core: &mut Core, ui: &mut Ui) {
+    let Some(tracks) = core.mpv_handler.ipc(|b| b.observed.tracks.clone()) else {
+        ui.label("Nothing is playing");
+        return;
+    };
+    for kind in [TrackKind::Audio, TrackKind::Video, TrackKind::Sub] {
+        let tracks: Vec<&TrackEntry> = tracks.iter().filter(|track| track.kind == kind).collect();
+        if tracks.is_empty() {
+            continue;
+        }
+        ui.horizontal(|ui| {
+            ui.heading(kind.label());
+            if kind == TrackKind::Audio
+                && tracks.len() > 1
+                && ui
+                    .button("Mix")
+                    .on_hover_text("Mix all audio tracks. Adjust the mix in the mixer.")
+                    .clicked()
+            {
+                core.apply_mixer().log_err("Failed to mix tracks");
+            }
+        });
+        egui::Grid::new(("tracks_grid", kind.label()))
+            .striped(true)
+            .show(ui, |ui| {
+                for track in tracks {
+                    track_row_ui(core, ui, track);
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+fn track_row_ui(core: &mut Core, ui: &mut Ui, track: &TrackEntry) {
+    let (kind, id) = (track.kind, track.id);
+    if ui
+        .selectable_label(track.selected, format!("#{id}"))
+        .on_hover_text("Switch to this track")
+        .clicked()
+    {
+        (core.mpv_handler.ipc(|b| b.select_track(kind, id)))
+            .unwrap_or(Ok(()))
+            .log_err("Failed to switch track");
+    }
+    ui.label(track.lang.as_deref().unwrap_or("-"));
+    ui.label(track.codec.as_deref().unwrap_or("-"));
+    ui.label(track.title.as_deref().unwrap_or_default());
+    ui.horizontal(|ui| {
+        if track.external && ui.button("🗑").on_hover_text("Remove this track").clicked() {
+            (core.mpv_handler.ipc(|b| b.remove_track(kind, id)))
+                .unwrap_or(Ok(()))
+                .log_err("Failed to remove track");
+        }
+    });
+}
@@ -0,0 +1,293 @@
+//! Building mpv's `af` (audio filter) chain
+
+use serde::{Deserialize, Serialize};
+
+/// Center frequencies (Hz) of the equalizer bands
+pub const EQ_BAND_FREQS: [u32; 10] = [31, 62, 125, 250, 500, 1000, 2000, 4000, 8000, 16000];
+
+/// A named set of equalizer settings. All gains are in dB.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EqPreset {
+    pub name: String,
+    /// Gain of each band in [`EQ_BAND_FREQS`]
+    pub bands: [f64; 10],
+    /// Low shelf gain
+    #[serde(default)]
+    pub bass: f64,
+    /// High shelf gain
+    #[serde(default)]
+    pub treble: f64,
+}
+
+impl EqPreset {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            bands: [0.0; _],
+            bass: 0.0,
+            treble: 0.0,
+        }
+    }
+    /// Append the filters of this preset to an `af` chain.
+    ///
+    /// Bands without gain are left out.
+    fn write_filters(&self, chain: &mut Vec<String>) {
+        for (idx, (freq, gain)) in EQ_BAND_FREQS.iter().zip(self.bands).enumerate() {
+            if gain != 0.0 {
+                // One octave wide peaking filter around each band
+                chain.push(format!(
+                    "@mpvfrog-eq{idx}:lavfi-equalizer=f={freq}:t=o:w=1:g={gain}"
+                ));
+            }
+        }
+        if self.bass != 0.0 {
+            chain.push(format!("@mpvfrog-bass:lavfi-bass=g={}", self.bass));
+        }
+        if self.treble != 0.0 {
+            chain.push(format!("@mpvfrog-treble:lavfi-treble=g={}", self.treble));
+        }
+    }
+}
+
+/// mpv's `--replaygain` modes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayGain {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+impl ReplayGain {
+    pub fn mpv_value(self) -> &'static str {
+        match self {
+            Self::Off => "no",
+            Self::Track => "track",
+            Self::Album => "album",
+        }
+    }
+}
+
+/// Loudness normalization through a filter, for files without ReplayGain tags
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    #[default]
+    Off,
+    /// EBU R128 loudness normalization
+    Loudnorm,
+    /// Dynamic normalization, which evens out loudness within the song too
+    Dynaudnorm,
+}
+
+impl Normalization {
+    fn filter(self) -> Option<&'static str> {
+        match self {
+            Self::Off => None,
+            Self::Loudnorm => Some("lavfi-loudnorm"),
+            Self::Dynaudnorm => Some("lavfi-dynaudnorm"),
+        }
+    }
+}
+
+/// Label of the `astats` filter that measures the audio levels.
+///
+/// mpv reports its measurements in the `af-metadata/<label>` property.
+pub const LEVELS_LABEL: &str = "mpvfrog-levels";
+
+/// Level of an audio channel, in dBFS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLevel {
+    pub rms: f64,
+    pub peak: f64,
+}
+
+/// Read the level of each channel out of the metadata of the `astats` filter
+pub fn levels_from_astats(
+    metadata: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<ChannelLevel> {
+    let get = |channel: usize, key: &str| -> Option<f64> {
+        let value = metadata.get(&format!("lavfi.astats.{channel}.{key}"))?;
+        // Silence is reported as "-inf"
+        value.as_str()?.parse().ok()
+    };
+    // Channels are numbered from 1
+    (1..)
+        .map_while(|channel| {
+            Some(ChannelLevel {
+                rms: get(channel, "RMS_level")?,
+                peak: get(channel, "Peak_level")?,
+            })
+        })
+        .collect()
+}
+
+/// Build the chain of audio filters mpvfrog adds to mpv's. Empty if there are no filters.
+///
+/// Every filter is labelled with an `@mpvfrog-` label, so they can be removed again without
+/// touching the filters the user added with `--af`.
+/// `pitch_semitones` shifts the pitch without changing the speed.
+/// If `levels` is true, the audio levels get measured (see [`LEVELS_LABEL`]).
+pub fn af_chain(
+    eq: Option<&EqPreset>,
+    pitch_semitones: f64,
+    normalization: Normalization,
+    levels: bool,
+) -> String {
+    let mut filters = Vec::new();
+    if let Some(eq) = eq {
+        eq.write_filters(&mut filters);
+    }
+    if pitch_semitones != 0.0 {
+        let ratio = 2f64.powf(pitch_semitones / 12.0);
+        filters.push(format!("@mpvfrog-pitch:lavfi-rubberband=pitch={ratio}"));
+    }
+    // Normalize last, so the equalizer can't push the volume back up
+    if let Some(filter) = normalization.filter() {
+        filters.push(format!("@mpvfrog-norm:{filter}"));
+    }
+    // Measure last, so we see the levels of what is actually heard
+    if levels {
+        filters.push(format!("@{LEVELS_LABEL}:lavfi-astats=metadata=1:reset=1"));
+    }
+    filters.join(",")
+}
+
+/// Settings of an audio track in the mixer
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixTrack {
+    /// mpv's id of the audio track
+    pub id: u64,
+    /// Gain in dB
+    pub gain: f64,
+    pub mute: bool,
+    /// If any track is soloed, only soloed tracks are heard
+    pub solo: bool,
+    /// Seconds to delay the track by. Negative values skip the start of the track instead.
+    pub offset: f64,
+}
+
+impl MixTrack {
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            gain: 0.0,
+            mute: false,
+            solo: false,
+            offset: 0.0,
+        }
+    }
+    /// The filters applied to this track before mixing
+    fn filters(&self) -> String {
+        let mut filters = vec![format!("volume={}dB", self.gain)];
+        if self.offset > 0.0 {
+            let ms = (self.offset * 1000.0).round();
+            filters.push(format!("adelay=delays={ms}:all=1"));
+        } else if self.offset < 0.0 {
+            filters.push(format!("atrim=start={},asetpts=PTS-STARTPTS", -self.offset));
+        }
+        filters.join(",")
+    }
+}
+
+/// Build the value of mpv's `lavfi-complex` property for mixing `tracks`
+pub fn mix_graph(tracks: &[MixTrack]) -> String {
+    let any_solo = tracks.iter().any(|track| track.solo);
+    let audible: Vec<&MixTrack> = tracks
+        .iter()
+        .filter(|track| !track.mute && (!any_solo || track.solo))
+        .collect();
+    match audible[..] {
+        [] => match tracks.first() {
+            // Silence, but the graph still needs an input
+            Some(track) => format!("[aid{}] volume=0 [ao]", track.id),
+            None => String::new(),
+        },
+        [track] => format!("[aid{}] {} [ao]", track.id, track.filters()),
+        _ => {
+            let mut graph = String::new();
+            for (i, track) in audible.iter().enumerate() {
+                graph.push_str(&format!("[aid{}] {} [m{i}]; ", track.id, track.filters()));
+            }
+            for i in 0..audible.len() {
+                graph.push_str(&format!("[m{i}] "));
+            }
+            // Don't scale the inputs down, the gains are up to the user
+            graph.push_str(&format!("amix=inputs={}:normalize=0 [ao]", audible.len()));
+            graph
+        }
+    }
+}
+
+#[test]
+fn test_af_chain() {
+    assert_eq!(af_chain(None, 0.0, Normalization::Off, false), "");
+    let mut eq = EqPreset::new("Test".into());
+    assert_eq!(af_chain(Some(&eq), 0.0, Normalization::Off, false), "");
+    assert_eq!(
+        af_chain(Some(&eq), 0.0, Normalization::Dynaudnorm, false),
+        "@mpvfrog-norm:lavfi-dynaudnorm"
+    );
+    assert_eq!(
+        af_chain(None, 12.0, Normalization::Off, false),
+        "@mpvfrog-pitch:lavfi-rubberband=pitch=2"
+    );
+    assert_eq!(
+        af_chain(None, 0.0, Normalization::Loudnorm, true),
+        "@mpvfrog-norm:lavfi-loudnorm,@mpvfrog-levels:lavfi-astats=metadata=1:reset=1"
+    );
+    eq.bands[0] = 3.0;
+    eq.bands[5] = -2.5;
+    eq.treble = 1.5;
+    assert_eq!(
+        af_chain(Some(&eq), 0.0, Normalization::Loudnorm, false),
+        "@mpvfrog-eq0:lavfi-equalizer=f=31:t=o:w=1:g=3,\
+         @mpvfrog-eq5:lavfi-equalizer=f=1000:t=o:w=1:g=-2.5,@mpvfrog-treble:lavfi-treble=g=1.5,\
+         @mpvfrog-norm:lavfi-loudnorm"
+    );
+}
+
+#[test]
+fn test_mix_graph() {
+    let mut tracks = vec![MixTrack::new(1), MixTrack::new(2)];
+    assert_eq!(
+        mix_graph(&tracks),
+        "[aid1] volume=0dB [m0]; [aid2] volume=0dB [m1]; [m0] [m1] amix=inputs=2:normalize=0 [ao]"
+    );
+    tracks[0].gain = -6.0;
+    tracks[1].offset = 0.25;
+    assert_eq!(
+        mix_graph(&tracks),
+        "[aid1] volume=-6dB [m0]; [aid2] volume=0dB,adelay=delays=250:all=1 [m1]; \
+         [m0] [m1] amix=inputs=2:normalize=0 [ao]"
+    );
+    tracks[1].offset = -1.5;
+    tracks[1].solo = true;
+    assert_eq!(
+        mix_graph(&tracks),
+        "[aid2] volume=0dB,atrim=start=1.5,asetpts=PTS-STARTPTS [ao]"
+    );
+    tracks[1].mute = true;
+    assert_eq!(mix_graph(&tracks), "[aid1] volume=0 [ao]");
+    assert_eq!(mix_graph(&[]), "");
+}
+
+#[test]
+fn test_levels_from_astats() {
+    let metadata = serde_json::json!({
+        "lavfi.astats.1.RMS_level": "-20.5",
+        "lavfi.astats.1.Peak_level": "-3.25",
+        "lavfi.astats.2.RMS_level": "-inf",
+        "lavfi.astats.2.Peak_level": "-inf",
+        "lavfi.astats.Overall.RMS_level": "-23.1",
+    });
+    let levels = levels_from_astats(metadata.as_object().unwrap());
+    assert_eq!(levels.len(), 2);
+    assert_eq!(
+        levels[0],
+        ChannelLevel {
+            rms: -20.5,
+            peak: -3.25
+        }
+    );
+    assert_eq!(levels[1].rms, f64::NEG_INFINITY);
+}
@@ -1,10 +1,13 @@
 //! Persistent configuration for the application
 
 use {
+    crate::audio_filter::{EqPreset, Normalization, ReplayGain},
+    chrono::NaiveTime,
     directories::ProjectDirs,
     enum_kinds::EnumKind,
     serde::{Deserialize, Deserializer, Serialize},
     std::{
+        collections::BTreeMap,
         fmt::Display,
         path::{Path, PathBuf},
     },
@@ -37,6 +40,221 @@ pub struct Config {
     pub fallback_font_paths: Vec<String>,
     #[serde(default = "default_scan_max_depth")]
     pub scan_max_depth: u8,
+    /// How mpv is run
+    #[serde(default)]
+    pub backend: BackendKind,
+    /// The mpv executable to run
+    #[serde(default = "default_mpv_path")]
+    pub mpv_path: String,
+    /// Extra arguments passed to mpv for every song
+    #[serde(default)]
+    pub extra_mpv_args: Vec<String>,
+    /// Extra arguments passed to mpv for songs with certain extensions
+    #[serde(default)]
+    pub ext_arg_profiles: Vec<ExtArgProfile>,
+    /// Audio output device (mpv's `--audio-device`). `None` lets mpv pick.
+    #[serde(default)]
+    pub audio_device: Option<String>,
+    #[serde(default)]
+    pub eq_presets: Vec<EqPreset>,
+    /// Name of the equalizer preset to use, unless the song has a more specific one
+    #[serde(default)]
+    pub eq_preset: Option<String>,
+    /// Equalizer presets for songs in certain folders
+    #[serde(default)]
+    pub folder_eq_presets: Vec<FolderEqPreset>,
+    #[serde(default)]
+    pub replaygain: ReplayGain,
+    /// Gain (dB) applied on top of the ReplayGain gain
+    #[serde(default)]
+    pub replaygain_preamp: f64,
+    /// Lower the ReplayGain gain if it would cause clipping
+    #[serde(default = "default_true")]
+    pub replaygain_clip_prevention: bool,
+    #[serde(default)]
+    pub normalization: Normalization,
+    #[serde(default)]
+    pub speed_mode: SpeedMode,
+    /// Pitch shift in semitones, independent of the speed
+    #[serde(default)]
+    pub pitch_semitones: f64,
+    /// Remember the speed of each song, instead of using the same speed for every song
+    #[serde(default)]
+    pub remember_song_speed: bool,
+    /// Remembered song speeds, by path relative to the music folder
+    #[serde(default)]
+    pub song_speeds: BTreeMap<PathBuf, f64>,
+    /// Show the waveform of the song in the seek bar (needs ffmpeg)
+    #[serde(default = "default_true")]
+    pub waveform: bool,
+    /// Measure the audio levels and show them in a meter
+    #[serde(default)]
+    pub level_meter: bool,
+    /// Also show the level meter in the tray popup
+    #[serde(default)]
+    pub level_meter_in_tray: bool,
+    /// Show the cover art of the song, from an image in its folder or embedded in it
+    #[serde(default = "default_true")]
+    pub cover_art: bool,
+    /// Use the cover art as the tray icon
+    #[serde(default)]
+    pub cover_art_tray_icon: bool,
+    /// Show whether a song is playing, paused or stopped on the tray icon
+    #[serde(default = "default_true")]
+    pub tray_icon_state: bool,
+    /// Saved loop regions and cue points, by song path relative to the music folder
+    #[serde(default)]
+    pub bookmarks: BTreeMap<PathBuf, Vec<Bookmark>>,
+    /// Key that jumps to the previous bookmark of the song
+    #[serde(default = "default_bookmark_prev_key")]
+    pub bookmark_prev_key: String,
+    /// Key that jumps to the next bookmark of the song
+    #[serde(default = "default_bookmark_next_key")]
+    pub bookmark_next_key: String,
+    #[serde(default)]
+    pub practice: PracticeSettings,
+    #[serde(default)]
+    pub sleep_timer: SleepTimerSettings,
+    #[serde(default)]
+    pub alarms: Vec<Alarm>,
+    /// Listen for JSON commands on a local socket, for scripts and status bars
+    #[serde(default)]
+    pub control_socket: bool,
+    /// Show a desktop notification when a song starts
+    #[serde(default)]
+    pub notifications: bool,
+    /// Template of the tray tooltip text
+    #[serde(default = "default_tooltip_template")]
+    pub tooltip_template: String,
+    /// File or FIFO to keep writing the now playing status to
+    #[serde(default)]
+    pub status_file: Option<PathBuf>,
+    /// Template of the text written to `status_file`
+    #[serde(default = "default_status_template")]
+    pub status_template: String,
+}
+
+/// What the sleep timer controls start with
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SleepTimerSettings {
+    pub minutes: u32,
+    pub songs: u32,
+    /// Fade the volume out over the last minute
+    pub fade_out: bool,
+}
+
+impl Default for SleepTimerSettings {
+    fn default() -> Self {
+        Self {
+            minutes: 30,
+            songs: 3,
+            fade_out: true,
+        }
+    }
+}
+
+/// Settings of practice mode, which speeds up each time the A-B loop repeats
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PracticeSettings {
+    pub start_speed: f64,
+    pub target_speed: f64,
+    /// How much the speed goes up each iteration
+    pub step: f64,
+    /// Seconds of silence before each iteration
+    pub count_in: f64,
+}
+
+impl Default for PracticeSettings {
+    fn default() -> Self {
+        Self {
+            start_speed: 0.7,
+            target_speed: 1.0,
+            step: 0.05,
+            count_in: 0.0,
+        }
+    }
+}
+
+/// Playback scheduled for a time of the day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alarm {
+    pub enabled: bool,
+    /// Local time
+    pub time: NaiveTime,
+    /// A song, a playlist file, or a folder to play from the start of
+    pub path: PathBuf,
+    /// Volume to fade in to
+    pub volume: u8,
+    /// Seconds the fade-in takes
+    pub fade_in: f64,
+    /// Disable the alarm once it went off
+    pub once: bool,
+}
+
+impl Default for Alarm {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            time: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            path: PathBuf::new(),
+            volume: 75,
+            fade_in: 60.0,
+            once: false,
+        }
+    }
+}
+
+/// A named position or loop region in a song
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    /// Position (in seconds) of the cue point, or the start of the loop region
+    pub a: f64,
+    /// End of the loop region. `None` for cue points.
+    pub b: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedMode {
+    /// Changing the speed keeps the pitch (mpv's `audio-pitch-correction`)
+    #[default]
+    TimeStretch,
+    /// Changing the speed changes the pitch too, like a tape
+    Tape,
+}
+
+impl SpeedMode {
+    pub fn pitch_correction(self) -> bool {
+        self == Self::TimeStretch
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FolderEqPreset {
+    /// Relative to the music folder
+    pub folder: PathBuf,
+    pub preset: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ExtArgProfile {
+    pub exts: HasExtsPredicate,
+    pub args: Vec<String>,
+}
+
+impl ExtArgProfile {
+    pub fn matches(&self, path: &Path) -> bool {
+        Predicate::matches_exts(&self.exts.ext_list, path, self.exts.case_sensitive)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Spawn mpv as a process, and control it through its IPC socket
+    #[default]
+    Process,
+    /// Run mpv in-process through libmpv (requires the `libmpv` feature)
+    Libmpv,
 }
 
 fn default_scan_max_depth() -> u8 {
@@ -56,10 +274,67 @@ impl Default for Config {
             skip_hidden: false,
             fallback_font_paths: Vec::new(),
             scan_max_depth: default_scan_max_depth(),
+            backend: BackendKind::default(),
+            mpv_path: default_mpv_path(),
+            extra_mpv_args: Vec::new(),
+            ext_arg_profiles: Vec::new(),
+            audio_device: None,
+            eq_presets: Vec::new(),
+            eq_preset: None,
+            folder_eq_presets: Vec::new(),
+            replaygain: ReplayGain::default(),
+            replaygain_preamp: 0.0,
+            replaygain_clip_prevention: true,
+            normalization: Normalization::default(),
+            speed_mode: SpeedMode::default(),
+            pitch_semitones: 0.0,
+            remember_song_speed: false,
+            song_speeds: BTreeMap::new(),
+            waveform: true,
+            level_meter: false,
+            level_meter_in_tray: false,
+            cover_art: true,
+            cover_art_tray_icon: false,
+            tray_icon_state: true,
+            bookmarks: BTreeMap::new(),
+            bookmark_prev_key: default_bookmark_prev_key(),
+            bookmark_next_key: default_bookmark_next_key(),
+            practice: PracticeSettings::default(),
+            sleep_timer: SleepTimerSettings::default(),
+            alarms: Vec::new(),
+            control_socket: false,
+            notifications: false,
+            tooltip_template: default_tooltip_template(),
+            status_file: None,
+            status_template: default_status_template(),
         }
     }
 }
 
+fn default_bookmark_prev_key() -> String {
+    "(".into()
+}
+
+fn default_bookmark_next_key() -> String {
+    ")".into()
+}
+
+fn default_tooltip_template() -> String {
+    "{file}\n{mpv}".into()
+}
+
+fn default_status_template() -> String {
+    "{artist} - {title} [{pos}/{dur}]".into()
+}
+
+fn default_mpv_path() -> String {
+    "mpv".into()
+}
+
+const fn default_true() -> bool {
+    true
+}
+
 const fn default_volume() -> u8 {
     50
 }
@@ -78,6 +353,52 @@ impl Config {
         let this = serde_json::from_str(&string)?;
         Ok(this)
     }
+    pub fn eq_preset_by_name(&self, name: &str) -> Option<&EqPreset> {
+        self.eq_presets.iter().find(|preset| preset.name == name)
+    }
+    /// A name starting with `base` that no equalizer preset has yet
+    pub fn unused_eq_preset_name(&self, base: &str) -> String {
+        std::iter::once(base.to_owned())
+            .chain((2..).map(|n| format!("{base} {n}")))
+            .find(|name| self.eq_preset_by_name(name).is_none())
+            .unwrap()
+    }
+    /// Rename an equalizer preset, along with everything that refers to it.
+    ///
+    /// Fails if the new name is empty, or another preset already has it.
+    pub fn rename_eq_preset(&mut self, old: &str, new: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(!new.trim().is_empty(), "Preset name can't be empty");
+        anyhow::ensure!(
+            new == old || self.eq_preset_by_name(new).is_none(),
+            "There is already a preset named \"{new}\""
+        );
+        let refs = (self.eq_preset.iter_mut())
+            .chain(self.folder_eq_presets.iter_mut().map(|en| &mut en.preset))
+            .chain((self.custom_demuxers.iter_mut()).filter_map(|en| en.eq_preset.as_mut()));
+        for name in refs {
+            if name == old {
+                new.clone_into(name);
+            }
+        }
+        if let Some(preset) = self.eq_presets.iter_mut().find(|preset| preset.name == old) {
+            new.clone_into(&mut preset.name);
+        }
+        Ok(())
+    }
+    /// Remove an equalizer preset, along with everything that refers to it.
+    ///
+    /// Folders that used it are forgotten, other references fall back to no preset.
+    pub fn remove_eq_preset(&mut self, name: &str) {
+        self.eq_presets.retain(|preset| preset.name != name);
+        self.folder_eq_presets.retain(|en| en.preset != name);
+        let refs = std::iter::once(&mut self.eq_preset)
+            .chain(self.custom_demuxers.iter_mut().map(|en| &mut en.eq_preset));
+        for preset in refs {
+            if preset.as_deref() == Some(name) {
+                *preset = None;
+            }
+        }
+    }
     pub fn path() -> PathBuf {
         let proj_dirs = ProjectDirs::from("", "crumblingstatue", "mpvfrog").unwrap();
         let cfg_dir = proj_dirs.config_dir();
@@ -129,6 +450,9 @@ pub struct CustomDemuxerEntry {
     pub extra_mpv_args: Vec<String>,
     #[serde(default)]
     pub name: String,
+    /// Equalizer preset to use for songs played with this demuxer
+    #[serde(default)]
+    pub eq_preset: Option<String>,
 }
 #[derive(thiserror::Error, Debug)]
 #[error("parse error: {kind}")]
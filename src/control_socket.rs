@@ -0,0 +1,393 @@
+//! Local socket for controlling mpvfrog from scripts and status bars
+//!
+//! Speaks newline-delimited JSON, much like mpv's own IPC.
+//! The protocol is described in the readme.
+
+use {
+    crate::{
+        app::App,
+        config::Config,
+        logln,
+        remote::{PlayState, RemoteCmd, Status},
+    },
+    anyhow::Context as _,
+    directories::ProjectDirs,
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    std::{
+        io::{ErrorKind, Read as _, Write as _},
+        os::unix::net::{UnixListener, UnixStream},
+        path::PathBuf,
+    },
+};
+
+/// How much output a client can leave unread before it gets dropped
+const MAX_PENDING: usize = 16 << 20;
+
+/// A command sent over the control socket.
+///
+/// Besides these, all the commands of the command line remote control are accepted.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "command")]
+pub enum SocketCmd {
+    /// Start getting events when the state changes
+    Subscribe,
+    Unsubscribe,
+    GetPlaylist,
+    /// Play the song at this index of the playlist
+    PlayIndex {
+        index: usize,
+    },
+    GetConfig,
+    /// Set a field of the config
+    SetConfig {
+        key: String,
+        value: Value,
+    },
+    #[serde(untagged)]
+    Remote(RemoteCmd),
+}
+
+#[derive(Deserialize, Debug)]
+struct Request {
+    #[serde(flatten)]
+    cmd: SocketCmd,
+    /// Sent back along with the reply, so clients can match them up
+    request_id: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Reply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<Value>,
+    /// `"success"`, or the error message
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+/// Sent to subscribed clients when the state changes. Named after what changed.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum Event {
+    Song { path: Option<PathBuf> },
+    State { state: PlayState },
+    Volume { volume: u8 },
+    Speed { speed: f64 },
+}
+
+/// What changed from `old` to `new`
+pub fn events_between(old: &Status, new: &Status) -> Vec<Event> {
+    let mut events = Vec::new();
+    if old.path != new.path {
+        events.push(Event::Song {
+            path: new.path.clone(),
+        });
+    }
+    if old.state != new.state {
+        events.push(Event::State { state: new.state });
+    }
+    if old.volume != new.volume {
+        events.push(Event::Volume { volume: new.volume });
+    }
+    if old.speed != new.speed {
+        events.push(Event::Speed { speed: new.speed });
+    }
+    events
+}
+
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<Client>,
+    /// To tell what changed since the last update
+    last_status: Status,
+}
+
+struct Client {
+    stream: UnixStream,
+    /// Received bytes that don't make up a whole line yet
+    buf: Vec<u8>,
+    subscribed: bool,
+    /// Output the client hasn't taken yet
+    out: Vec<u8>,
+}
+
+impl ControlServer {
+    pub fn bind() -> anyhow::Result<Self> {
+        let path = socket_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Only one instance runs the control socket, so this can only be left over from a crash
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            path,
+            clients: Vec::new(),
+            last_status: Status::default(),
+        })
+    }
+    /// Accept new clients, handle their requests, and send out events
+    pub fn update(&mut self, app: &mut App) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match Client::new(stream) {
+                    Ok(client) => self.clients.push(client),
+                    Err(e) => logln!("Control socket error: {e}"),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    logln!("Control socket error: {e}");
+                    break;
+                }
+            }
+        }
+        self.clients
+            .retain_mut(|client| client.handle_requests(app).is_ok());
+        let status = app.status();
+        let events = events_between(&self.last_status, &status);
+        self.last_status = status;
+        self.clients.retain_mut(|client| {
+            (!client.subscribed || events.iter().all(|event| client.send(event).is_ok()))
+                && client.flush().is_ok()
+        });
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Client {
+    fn new(stream: UnixStream) -> std::io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            buf: Vec::new(),
+            subscribed: false,
+            out: Vec::new(),
+        })
+    }
+    /// Handle all the requests received so far. Errors mean the client should be dropped.
+    fn handle_requests(&mut self, app: &mut App) -> std::io::Result<()> {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        while let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_slice::<Request>(&line) {
+                Ok(req) => {
+                    let result = self.exec(app, req.cmd);
+                    Reply {
+                        request_id: req.request_id,
+                        error: result
+                            .as_ref()
+                            .err()
+                            .map_or_else(|| "success".into(), |e| format!("{e:#}")),
+                        data: result.ok().flatten(),
+                    }
+                }
+                Err(e) => Reply {
+                    request_id: None,
+                    error: format!("Invalid request: {e}"),
+                    data: None,
+                },
+            };
+            self.send(&reply)?;
+        }
+        Ok(())
+    }
+    fn exec(&mut self, app: &mut App, cmd: SocketCmd) -> anyhow::Result<Option<Value>> {
+        match cmd {
+            SocketCmd::Subscribe => self.subscribed = true,
+            SocketCmd::Unsubscribe => self.subscribed = false,
+            SocketCmd::GetPlaylist => return Ok(Some(playlist_json(app))),
+            SocketCmd::PlayIndex { index } => {
+                anyhow::ensure!(index < app.core.playlist.len(), "No song at index {index}");
+                app.focus_and_play(index);
+            }
+            SocketCmd::GetConfig => return Ok(Some(serde_json::to_value(&app.core.cfg)?)),
+            SocketCmd::SetConfig { key, value } => set_config(app, &key, value)?,
+            SocketCmd::Remote(cmd) => return app.exec_remote(cmd),
+        }
+        Ok(None)
+    }
+    /// Queue up a message. It's sent by [`Self::flush`].
+    fn send(&mut self, msg: &impl Serialize) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.out, msg)?;
+        self.out.push(b'\n');
+        // A client that doesn't read what it's sent would otherwise make this grow forever
+        if self.out.len() > MAX_PENDING {
+            return Err(std::io::Error::other("Client doesn't read its output"));
+        }
+        Ok(())
+    }
+    /// Send as much of the queued output as the socket takes without blocking
+    fn flush(&mut self) -> std::io::Result<()> {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => drop(self.out.drain(..n)),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn playlist_json(app: &App) -> Value {
+    let songs: Vec<Value> = (app.core.playlist.iter())
+        .map(|item| serde_json::json!({"path": item.path, "label": item.label()}))
+        .collect();
+    serde_json::json!({"selected": app.core.selected_song, "songs": songs})
+}
+
+/// Set the config field named `key` to `value`, the same way changing it in the UI would
+fn set_config(app: &mut App, key: &str, value: Value) -> anyhow::Result<()> {
+    let core = &mut app.core;
+    let cfg = with_field(&core.cfg, key, value)?;
+    match key {
+        "volume" => {
+            core.cfg.volume = cfg.volume;
+            (core.mpv_handler.ipc(|b| b.set_volume(cfg.volume))).unwrap_or(Ok(()))
+        }
+        // Same as the `speed` command
+        "speed" => match core.mpv_handler.ipc(|b| b.set_speed(cfg.speed)) {
+            Some(result) => result,
+            None => {
+                core.set_stopped_speed(cfg.speed);
+                Ok(())
+            }
+        },
+        "speed_mode" => core.set_speed_mode(cfg.speed_mode),
+        "level_meter" => core.set_level_meter(cfg.level_meter),
+        "audio_device" => core.set_audio_device(cfg.audio_device),
+        "eq_preset" => core.set_eq_preset(cfg.eq_preset),
+        "replaygain"
+        | "replaygain_preamp"
+        | "replaygain_clip_prevention"
+        | "normalization"
+        | "pitch_semitones"
+        | "eq_presets"
+        | "folder_eq_presets" => {
+            core.cfg = cfg;
+            core.apply_loudness_settings()
+        }
+        _ => {
+            core.cfg = cfg;
+            Ok(())
+        }
+    }
+}
+
+/// `cfg` with the field named `key` set to `value`
+fn with_field(cfg: &Config, key: &str, value: Value) -> anyhow::Result<Config> {
+    let mut json = serde_json::to_value(cfg)?;
+    let field = json
+        .get_mut(key)
+        .with_context(|| format!("No config field named `{key}`"))?;
+    *field = value;
+    Ok(serde_json::from_value(json)?)
+}
+
+/// `$XDG_RUNTIME_DIR/mpvfrog/control.sock`, or a per-user path in `/tmp` without a runtime dir
+pub fn socket_path() -> PathBuf {
+    ProjectDirs::from("", "crumblingstatue", "mpvfrog")
+        .and_then(|dirs| dirs.runtime_dir().map(|dir| dir.join("control.sock")))
+        .unwrap_or_else(|| {
+            let uid = rustix::process::getuid().as_raw();
+            format!("/tmp/mpvfrog-{uid}-control.sock").into()
+        })
+}
+
+#[test]
+fn test_requests() {
+    let parse = |json: &str| serde_json::from_str::<Request>(json).unwrap();
+    let req = parse(r#"{"command": "subscribe", "request_id": 3}"#);
+    assert_eq!(req.cmd, SocketCmd::Subscribe);
+    assert_eq!(req.request_id, Some(3.into()));
+    let req = parse(r#"{"command": "volume", "volume": 60}"#);
+    assert_eq!(req.cmd, SocketCmd::Remote(RemoteCmd::Volume { volume: 60 }));
+    assert_eq!(req.request_id, None);
+    let req = parse(r#"{"command": "set_config", "key": "volume", "value": 40}"#);
+    let SocketCmd::SetConfig { key, value } = req.cmd else {
+        panic!("{:?}", req.cmd);
+    };
+    let cfg = with_field(&Config::default(), &key, value).unwrap();
+    assert_eq!(cfg.volume, 40);
+    assert!(with_field(&cfg, "no_such_key", 1.into()).is_err());
+    assert!(with_field(&cfg, "volume", "loud".into()).is_err());
+    assert!(serde_json::from_str::<Request>(r#"{"command": "dance"}"#).is_err());
+}
+
+#[test]
+fn test_events() {
+    let old = Status::default();
+    let new = Status {
+        state: PlayState::Playing,
+        path: Some("/music/a.mp3".into()),
+        volume: 50,
+        ..Default::default()
+    };
+    let events = events_between(&old, &new);
+    assert_eq!(
+        events,
+        [
+            Event::Song {
+                path: Some("/music/a.mp3".into())
+            },
+            Event::State {
+                state: PlayState::Playing
+            },
+            Event::Volume { volume: 50 },
+        ]
+    );
+    assert_eq!(
+        serde_json::to_string(&events[1]).unwrap(),
+        r#"{"event":"state","state":"playing"}"#
+    );
+    assert!(events_between(&new, &new).is_empty());
+}
+
+#[test]
+fn test_client_output() {
+    let (stream, mut other_end) = UnixStream::pair().unwrap();
+    let mut client = Client::new(stream).unwrap();
+    // Much more than fits in the socket buffer, which must not block
+    let big = "x".repeat(1 << 20);
+    client.send(&big).unwrap();
+    client.flush().unwrap();
+    assert!(!client.out.is_empty());
+    let expected = format!("\"{big}\"\n");
+    let mut received = Vec::new();
+    while received.len() < expected.len() {
+        let mut chunk = [0; 4096];
+        let n = other_end.read(&mut chunk).unwrap();
+        received.extend_from_slice(&chunk[..n]);
+        client.flush().unwrap();
+    }
+    assert_eq!(received, expected.as_bytes());
+    assert!(client.out.is_empty());
+    for _ in 0..MAX_PENDING / expected.len() {
+        client.send(&big).unwrap();
+    }
+    assert!(client.send(&big).is_err());
+}
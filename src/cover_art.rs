@@ -0,0 +1,200 @@
+//! Finding the cover art of songs
+
+use {
+    crate::util::cache::song_cache_path,
+    anyhow::Context as _,
+    std::{
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+        sync::mpsc::Receiver,
+    },
+};
+
+/// Width and height cover art is decoded at
+pub const SIZE: usize = 256;
+
+/// File names (without extension) that cover images usually have
+const COVER_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Cover art of a song, decoded to [`SIZE`]×[`SIZE`] RGBA pixels
+pub struct CoverArt {
+    /// The image it was decoded from
+    pub path: PathBuf,
+    pub rgba: Vec<u8>,
+}
+
+impl CoverArt {
+    /// Scaled down to `size`×`size` ARGB32 pixels in network byte order, like
+    /// StatusNotifierItem icons are. `size` has to divide [`SIZE`].
+    pub fn argb32(&self, size: usize) -> Vec<u8> {
+        let scale = SIZE / size;
+        let mut out = Vec::with_capacity(size * size * 4);
+        for y in 0..size {
+            for x in 0..size {
+                let mut sum = [0_u32; 4];
+                for sy in y * scale..(y + 1) * scale {
+                    let row = sy * SIZE * 4;
+                    let pixels = &self.rgba[row + x * scale * 4..row + (x + 1) * scale * 4];
+                    for pixel in pixels.chunks_exact(4) {
+                        for (sum, &c) in sum.iter_mut().zip(pixel) {
+                            *sum += u32::from(c);
+                        }
+                    }
+                }
+                let [r, g, b, a] = sum.map(|sum| (sum / (scale * scale) as u32) as u8);
+                out.extend_from_slice(&[a, r, g, b]);
+            }
+        }
+        out
+    }
+}
+
+/// Find and decode the cover art of a song in the background.
+///
+/// Songs without cover art result in `None`.
+pub fn load(song: PathBuf) -> Receiver<anyhow::Result<Option<CoverArt>>> {
+    let (send, recv) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let result = find(&song)
+            .map(|path| {
+                Ok(CoverArt {
+                    rgba: decode(&path)?,
+                    path,
+                })
+            })
+            .transpose();
+        // The receiver might have been dropped in the meantime, if the song was changed
+        let _ = send.send(result);
+    });
+    recv
+}
+
+/// Decode an image with ffmpeg, fitting it into [`SIZE`]×[`SIZE`] RGBA pixels
+fn decode(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let filter = format!(
+        "scale={SIZE}:{SIZE}:force_original_aspect_ratio=decrease,format=rgba,\
+         pad={SIZE}:{SIZE}:-1:-1:color=black@0"
+    );
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-vf", &filter])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .context("Failed to run ffmpeg")?;
+    anyhow::ensure!(output.status.success(), "ffmpeg failed ({})", output.status);
+    anyhow::ensure!(
+        output.stdout.len() == SIZE * SIZE * 4,
+        "ffmpeg decoded {} bytes instead of a {SIZE}x{SIZE} image",
+        output.stdout.len()
+    );
+    Ok(output.stdout)
+}
+
+/// Image file next to the song, or else the picture embedded in it
+pub fn find(song: &Path) -> Option<PathBuf> {
+    folder_image(song).or_else(|| embedded_image(song).ok())
+}
+
+/// An image like `cover.jpg` in the folder of the song, or the only image there is
+pub fn folder_image(song: &Path) -> Option<PathBuf> {
+    let mut images: Vec<PathBuf> = std::fs::read_dir(song.parent()?)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+        })
+        .collect();
+    images.sort();
+    let named = images.iter().position(|path| {
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| {
+                COVER_NAMES
+                    .iter()
+                    .any(|name| stem.eq_ignore_ascii_case(name))
+            })
+    });
+    match named {
+        Some(idx) => Some(images.swap_remove(idx)),
+        None if images.len() == 1 => images.pop(),
+        None => None,
+    }
+}
+
+/// Extract the picture embedded in the song as a png, with ffmpeg.
+///
+/// Extracted pictures are cached, so this is only slow the first time.
+pub fn embedded_image(song: &Path) -> anyhow::Result<PathBuf> {
+    let cache_path = song_cache_path(song, "covers")?.with_extension("png");
+    if cache_path.exists() {
+        return Ok(cache_path);
+    }
+    if let Some(dir) = cache_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Extract to a temporary file, so a failed extraction doesn't leave a broken image behind
+    let tmp_path = cache_path.with_extension("tmp");
+    let status = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-i"])
+        .arg(song)
+        .args(["-an", "-frames:v", "1", "-c:v", "png", "-f", "image2"])
+        .arg(&tmp_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run ffmpeg")?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&tmp_path);
+        anyhow::bail!("No embedded picture ({status})");
+    }
+    std::fs::rename(tmp_path, &cache_path)?;
+    Ok(cache_path)
+}
+
+#[test]
+fn test_folder_image() {
+    let dir = std::env::temp_dir().join(format!("mpvfrog-cover-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let song = dir.join("song.mp3");
+    let touch = |name: &str| std::fs::write(dir.join(name), b"").unwrap();
+    touch("song.mp3");
+    assert_eq!(folder_image(&song), None);
+    touch("scan.jpg");
+    assert_eq!(folder_image(&song), Some(dir.join("scan.jpg")));
+    touch("back.png");
+    assert_eq!(folder_image(&song), None);
+    touch("Folder.PNG");
+    assert_eq!(folder_image(&song), Some(dir.join("Folder.PNG")));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_argb32() {
+    // Left half opaque red, right half transparent
+    let rgba = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            if i % SIZE < SIZE / 2 {
+                [255, 0, 0, 255]
+            } else {
+                [0; 4]
+            }
+        })
+        .collect();
+    let art = CoverArt {
+        path: PathBuf::new(),
+        rgba,
+    };
+    let icon = art.argb32(2);
+    assert_eq!(
+        icon,
+        [255, 255, 0, 0, 0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(art.argb32(64).len(), 64 * 64 * 4);
+}
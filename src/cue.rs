@@ -0,0 +1,115 @@
+//! Reading the track list of cue sheets
+
+/// A track of a cue sheet.
+///
+/// mpv plays cue sheets as one file with a chapter per track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    /// Track number, as written in the cue sheet
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Index of the chapter mpv makes for this track
+    pub chapter: usize,
+    /// Whether this is the last track of the sheet
+    pub last: bool,
+}
+
+impl CueTrack {
+    /// Arguments that make mpv only play this track of the cue sheet
+    pub fn mpv_args(&self) -> Vec<String> {
+        // mpv's chapter numbers start from 1
+        let mut args = vec![format!("--start=#{}", self.chapter + 1)];
+        if !self.last {
+            args.push(format!("--end=#{}", self.chapter + 2));
+        }
+        args
+    }
+    pub fn label(&self) -> String {
+        let mut label = format!("{:02}", self.number);
+        if let Some(performer) = &self.performer {
+            label.push(' ');
+            label.push_str(performer);
+            label.push_str(" -");
+        }
+        if let Some(title) = &self.title {
+            label.push(' ');
+            label.push_str(title);
+        }
+        label
+    }
+}
+
+/// Parse the tracks out of a cue sheet. Unknown commands are ignored.
+pub fn parse_tracks(text: &str) -> Vec<CueTrack> {
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    for line in text.lines() {
+        let (cmd, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match cmd {
+            "TRACK" => {
+                let Some(number) = rest.split_whitespace().next().and_then(|n| n.parse().ok())
+                else {
+                    continue;
+                };
+                tracks.push(CueTrack {
+                    number,
+                    title: None,
+                    performer: None,
+                    chapter: tracks.len(),
+                    last: false,
+                });
+            }
+            // Before the first track, these belong to the whole album
+            "TITLE" => {
+                if let Some(track) = tracks.last_mut() {
+                    track.title = Some(unquote(rest).to_owned());
+                }
+            }
+            "PERFORMER" => {
+                if let Some(track) = tracks.last_mut() {
+                    track.performer = Some(unquote(rest).to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(track) = tracks.last_mut() {
+        track.last = true;
+    }
+    tracks
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+#[test]
+fn test_parse_tracks() {
+    let sheet = "\
+REM GENRE Rock
+PERFORMER \"The Band\"
+TITLE \"The Album\"
+FILE \"album.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Intro\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Second Song\"
+    PERFORMER \"Guest\"
+    INDEX 00 03:10:50
+    INDEX 01 03:12:00
+  TRACK 03 AUDIO
+    INDEX 01 07:00:00
+";
+    let tracks = parse_tracks(sheet);
+    assert_eq!(tracks.len(), 3);
+    assert_eq!(tracks[0].label(), "01 Intro");
+    assert_eq!(tracks[1].label(), "02 Guest - Second Song");
+    assert_eq!(tracks[2].label(), "03");
+    assert_eq!(tracks[0].mpv_args(), ["--start=#1", "--end=#2"]);
+    assert_eq!(tracks[2].mpv_args(), ["--start=#3"]);
+    assert!(parse_tracks("not a cue sheet").is_empty());
+}
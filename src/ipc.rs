@@ -1,17 +1,25 @@
 //! Interprocess comunication with spawned mpv process
 
 mod command;
+#[cfg(test)]
+pub mod fake_mpv;
 pub mod property;
+#[cfg(test)]
+mod tests;
 
 use {
-    crate::{logln, util::result_ext::LogErrExt as _},
-    command::{AudioAdd, AudioRemove, Command, ObserveProperty, SetProperty},
+    crate::{
+        audio_filter::{self, ChannelLevel},
+        logln,
+        util::result_ext::LogErrExt as _,
+    },
+    command::{AddProperty, Af, AudioAdd, Command, ObserveProperty, SetProperty, TrackRemove},
     interprocess::local_socket::{
         GenericFilePath, Stream as LocalSocketStream, ToFsName, traits::Stream as _,
     },
     property::{PropValue, Property},
     std::{
-        collections::{HashMap, VecDeque},
+        collections::{BTreeMap, VecDeque},
         io::{BufRead as _, BufReader, Write},
         marker::PhantomData,
     },
@@ -19,10 +27,115 @@ use {
 
 pub enum IpcEvent {
     EndFile,
+    /// The list of available audio output devices has changed
+    AudioDevices(Vec<AudioDeviceEntry>),
 }
 
-pub struct Bridge {
+/// An entry of mpv's `audio-device-list`
+#[derive(serde::Deserialize, Clone)]
+pub struct AudioDeviceEntry {
+    pub name: String,
+    pub description: String,
+}
+
+/// An entry of mpv's `track-list`
+#[derive(serde::Deserialize, Clone)]
+pub struct TrackEntry {
+    /// Id of the track among the tracks of the same kind
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: TrackKind,
+    pub title: Option<String>,
+    pub lang: Option<String>,
+    pub codec: Option<String>,
+    #[serde(default)]
+    pub selected: bool,
+    /// Whether the track was loaded from a separate file (e.g. with `audio-add`)
+    #[serde(default)]
+    pub external: bool,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackKind {
+    Audio,
+    Video,
+    Sub,
+}
+
+/// An entry of mpv's `chapter-list`
+#[derive(serde::Deserialize, Clone)]
+pub struct ChapterEntry {
+    #[serde(default)]
+    pub title: String,
+    /// Start of the chapter, in seconds
+    pub time: f64,
+}
+
+/// The channel a [`Bridge`] uses to talk to mpv
+pub trait Transport {
+    /// Send a command in mpv's JSON IPC format (`{"command": [...]}`)
+    fn send(&mut self, command: &serde_json::Value) -> anyhow::Result<()>;
+    /// Receive the next reply or event from mpv, if there is one. Doesn't block.
+    fn recv(&mut self) -> anyhow::Result<Option<serde_json::Value>>;
+}
+
+/// Newline delimited JSON over mpv's `--input-ipc-server` socket
+pub struct SocketTransport {
     ipc_stream: BufReader<LocalSocketStream>,
+    /// A message we have only received part of so far
+    partial: Vec<u8>,
+}
+
+impl SocketTransport {
+    pub fn connect(socket_path: &str) -> anyhow::Result<Self> {
+        let ipc_stream = LocalSocketStream::connect(socket_path.to_fs_name::<GenericFilePath>()?)?;
+        ipc_stream.set_nonblocking(true)?;
+        Ok(Self {
+            ipc_stream: BufReader::new(ipc_stream),
+            partial: Vec::new(),
+        })
+    }
+}
+
+impl Transport for SocketTransport {
+    fn send(&mut self, command: &serde_json::Value) -> anyhow::Result<()> {
+        let mut serialized = serde_json::to_vec(command)?;
+        // Commands need to be terminated with newline
+        serialized.push(b'\n');
+        self.ipc_stream.get_mut().write_all(&serialized)?;
+        Ok(())
+    }
+    fn recv(&mut self) -> anyhow::Result<Option<serde_json::Value>> {
+        loop {
+            // `read_until` keeps what it has read so far if the read would block,
+            // so a message that arrives in pieces gets completed on a later call.
+            match self.ipc_stream.read_until(b'\n', &mut self.partial) {
+                Ok(amount) => {
+                    if amount == 0 || self.partial.last() != Some(&b'\n') {
+                        // Assume EOF and return
+                        return Ok(None);
+                    }
+                    let buf = std::mem::take(&mut self.partial);
+                    match serde_json::from_slice(&buf) {
+                        Ok(msg) => return Ok(Some(msg)),
+                        Err(e) => {
+                            logln!("Serialize error: {}", e);
+                            logln!("Unserialized event: {}", String::from_utf8_lossy(&buf));
+                        }
+                    }
+                }
+                Err(e) => match e.kind() {
+                    std::io::ErrorKind::WouldBlock => return Ok(None),
+                    _ => anyhow::bail!("ipc bridge io error: {}", e),
+                },
+            }
+        }
+    }
+}
+
+pub struct Bridge {
+    transport: Box<dyn Transport>,
     pub observed: Properties,
     pub event_queue: VecDeque<IpcEvent>,
 }
@@ -36,22 +149,34 @@ pub struct Properties {
     pub time_pos: f64,
     pub ab_loop_a: Option<f64>,
     pub ab_loop_b: Option<f64>,
-    pub track_count: u8,
+    pub tracks: Vec<TrackEntry>,
     pub lavfi_complex: String,
     pub loop_file: bool,
     pub playlist_pos: u64,
     pub playlist_count: u64,
     pub seekable: bool,
+    pub chapters: Vec<ChapterEntry>,
+    /// Index of the current chapter. `None` before the first chapter.
+    pub chapter: Option<usize>,
+    /// Audio levels, if they are being measured
+    pub levels: Vec<ChannelLevel>,
+    /// Tags of the file, like artist and title
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Properties {
+    /// Look up a metadata tag. Files differ in how they capitalize them, so case is ignored.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        (self.metadata.iter())
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
 }
 
 impl Bridge {
-    pub fn connect() -> anyhow::Result<Self> {
-        let ipc_stream = LocalSocketStream::connect(
-            "/tmp/mpvfrog.sock".to_fs_name::<GenericFilePath>().unwrap(),
-        )?;
-        ipc_stream.set_nonblocking(true)?;
+    pub fn new(transport: Box<dyn Transport>) -> anyhow::Result<Self> {
         let mut this = Self {
-            ipc_stream: BufReader::new(ipc_stream),
+            transport,
             observed: Default::default(),
             event_queue: Default::default(),
         };
@@ -61,12 +186,17 @@ impl Bridge {
         this.observe_property::<property::Duration>()?;
         this.observe_property::<property::AbLoopA>()?;
         this.observe_property::<property::AbLoopB>()?;
-        this.observe_property::<property::TrackListCount>()?;
+        this.observe_property::<property::TrackList>()?;
         this.observe_property::<property::LavfiComplex>()?;
         this.observe_property::<property::LoopFile>()?;
         this.observe_property::<property::PlaylistPos>()?;
         this.observe_property::<property::PlaylistCount>()?;
         this.observe_property::<property::Seekable>()?;
+        this.observe_property::<property::AudioDeviceList>()?;
+        this.observe_property::<property::ChapterList>()?;
+        this.observe_property::<property::Chapter>()?;
+        this.observe_property::<property::LevelsMetadata>()?;
+        this.observe_property::<property::Metadata>()?;
         Ok(this)
     }
     pub fn observe_property<T: Property>(&mut self) -> anyhow::Result<()> {
@@ -81,13 +211,13 @@ impl Bridge {
         self.set_property::<property::Pause>(self.observed.paused)?;
         Ok(())
     }
+    pub fn set_pause(&mut self, pause: bool) -> anyhow::Result<()> {
+        self.observed.paused = pause;
+        self.set_property::<property::Pause>(pause)
+    }
     fn write_command<C: Command>(&mut self, command: C) -> anyhow::Result<()> {
-        let command_json = command.to_command_json();
-        let mut serialized = serde_json::to_vec(&command_json).unwrap();
-        // Commands need to be terminated with newline
-        serialized.push(b'\n');
-        self.ipc_stream.get_mut().write_all(&serialized)?;
-        Ok(())
+        let command_json = serde_json::to_value(command.to_command_json())?;
+        self.transport.send(&command_json)
     }
     pub fn set_property<P: Property>(&mut self, value: P::Value) -> anyhow::Result<()>
     where
@@ -96,54 +226,33 @@ impl Bridge {
         self.write_command(SetProperty::<P>(value))
     }
     pub fn handle_responses(&mut self) -> anyhow::Result<()> {
-        loop {
-            let mut buf = String::new();
-            match self.ipc_stream.read_line(&mut buf) {
-                Ok(amount) => {
-                    if amount == 0 {
-                        // Assume EOF and return
-                        return Ok(());
-                    }
-                    for line in buf.lines() {
-                        self.handle_response_line(line)
-                    }
-                }
-                Err(e) => match e.kind() {
-                    std::io::ErrorKind::WouldBlock => return Ok(()),
-                    _ => anyhow::bail!("ipc bridge io error: {}", e),
-                },
-            }
+        while let Some(msg) = self.transport.recv()? {
+            self.handle_message(&msg);
         }
+        Ok(())
     }
-    fn handle_response_line(&mut self, line: &str) {
-        match serde_json::from_str::<HashMap<&str, serde_json::Value>>(line) {
-            Ok(map) => {
-                if let Some(event) = map.get("event") {
-                    match event.as_str().unwrap() {
-                        "pause" => self.observed.paused = true,
-                        "unpause" => self.observed.paused = false,
-                        "property-change" => {
-                            let name = map.get("name").unwrap().as_str().unwrap();
-                            let Some(data) = map.get("data") else {
-                                logln!("data-less property change: {}", name);
-                                return;
-                            };
-                            if self.handle_property_change(name, data).is_none() {
-                                logln!(
-                                    "Failed to change property `{name}`: Type mismatch. Got {data}"
-                                );
-                            }
-                        }
-                        "end-file" => {
-                            self.event_queue.push_back(IpcEvent::EndFile);
-                        }
-                        _ => logln!("Unhandled event: {}", event),
+    fn handle_message(&mut self, msg: &serde_json::Value) {
+        if let Some(event) = msg.get("event") {
+            match event.as_str().unwrap_or_default() {
+                "pause" => self.observed.paused = true,
+                "unpause" => self.observed.paused = false,
+                "property-change" => {
+                    let Some(name) = msg.get("name").and_then(serde_json::Value::as_str) else {
+                        logln!("Nameless property change: {}", msg);
+                        return;
+                    };
+                    let Some(data) = msg.get("data") else {
+                        logln!("data-less property change: {}", name);
+                        return;
+                    };
+                    if self.handle_property_change(name, data).is_none() {
+                        logln!("Failed to change property `{name}`: Type mismatch. Got {data}");
                     }
                 }
-            }
-            Err(e) => {
-                logln!("Serialize error: {}", e);
-                logln!("Unserialized event: {}", line);
+                "end-file" => {
+                    self.event_queue.push_back(IpcEvent::EndFile);
+                }
+                _ => logln!("Unhandled event: {}", event),
             }
         }
     }
@@ -155,12 +264,30 @@ impl Bridge {
             property::TimePos::NAME => self.observed.time_pos = data.as_f64()?,
             property::AbLoopA::NAME => self.observed.ab_loop_a = data.as_f64(),
             property::AbLoopB::NAME => self.observed.ab_loop_b = data.as_f64(),
-            property::TrackListCount::NAME => self.observed.track_count = data.as_u64()? as u8,
+            property::TrackList::NAME => {
+                self.observed.tracks = serde_json::from_value(data.clone()).ok()?;
+            }
             property::LavfiComplex::NAME => self.observed.lavfi_complex = data.as_str()?.to_owned(),
             property::LoopFile::NAME => self.observed.loop_file = data.as_str() == Some("inf"),
             property::PlaylistCount::NAME => self.observed.playlist_count = data.as_u64()?,
             property::PlaylistPos::NAME => self.observed.playlist_pos = data.as_u64()?,
             property::Seekable::NAME => self.observed.seekable = data.as_bool()?,
+            property::ChapterList::NAME => {
+                self.observed.chapters = serde_json::from_value(data.clone()).ok()?;
+            }
+            // -1 before the first chapter
+            property::Chapter::NAME => self.observed.chapter = usize::try_from(data.as_i64()?).ok(),
+            // Null when nothing is loaded
+            property::Metadata::NAME => {
+                self.observed.metadata = serde_json::from_value(data.clone()).unwrap_or_default();
+            }
+            property::LevelsMetadata::NAME => {
+                self.observed.levels = audio_filter::levels_from_astats(data.as_object()?);
+            }
+            property::AudioDeviceList::NAME => {
+                let devices = serde_json::from_value(data.clone()).ok()?;
+                self.event_queue.push_back(IpcEvent::AudioDevices(devices));
+            }
             name => logln!("Unhandled property: {} = {}", name, data),
         }
         Some(())
@@ -171,9 +298,41 @@ impl Bridge {
     pub fn set_speed(&mut self, speed: f64) -> anyhow::Result<()> {
         self.set_property::<property::Speed>(speed)
     }
+    /// Whether changing the speed should keep the pitch
+    pub fn set_pitch_correction(&mut self, correct: bool) -> anyhow::Result<()> {
+        self.set_property::<property::AudioPitchCorrection>(correct)
+    }
     pub fn seek(&mut self, pos: f64) -> anyhow::Result<()> {
         self.set_property::<property::TimePos>(pos)
     }
+    pub fn set_chapter(&mut self, idx: usize) -> anyhow::Result<()> {
+        self.set_property::<property::Chapter>(idx as u64)
+    }
+    /// Go `delta` chapters forward or back
+    pub fn add_chapter(&mut self, delta: i8) -> anyhow::Result<()> {
+        self.write_command(AddProperty::<property::Chapter>(delta.into(), PhantomData))
+    }
+    /// Switch to another audio output device. `"auto"` lets mpv pick.
+    pub fn set_audio_device(&mut self, name: &str) -> anyhow::Result<()> {
+        self.set_property::<property::AudioDevice>(name.to_owned())
+    }
+    /// Swap the filters mpvfrog added to the audio filter chain from `old` to `new`.
+    ///
+    /// Filters are removed by their labels, so ones the user added with `--af` stay.
+    pub fn replace_af(&mut self, old: &str, new: &str) -> anyhow::Result<()> {
+        if !old.is_empty() {
+            self.write_command(Af("remove", old))?;
+        }
+        if !new.is_empty() {
+            self.write_command(Af("add", new))?;
+        }
+        Ok(())
+    }
+    pub fn set_replaygain(&mut self, mode: &str, preamp: f64, clip: bool) -> anyhow::Result<()> {
+        self.set_property::<property::ReplayGain>(mode.to_owned())?;
+        self.set_property::<property::ReplayGainPreamp>(preamp)?;
+        self.set_property::<property::ReplayGainClip>(clip)
+    }
     pub fn set_video(&mut self, show: bool) -> anyhow::Result<()> {
         self.set_property::<property::Video>(show.then_some("1"))
     }
@@ -181,14 +340,13 @@ impl Bridge {
         self.set_property::<property::AbLoopA>(a)?;
         self.set_property::<property::AbLoopB>(b)
     }
+    /// Load an audio file as an additional audio track
     pub fn add_audio(&mut self, path: &str) -> anyhow::Result<()> {
-        self.write_command(AudioAdd(path))?;
-        self.set_property::<property::LavfiComplex>("[aid1] [aid2] amix [ao]".into())
+        self.write_command(AudioAdd(path))
     }
 
-    pub fn mix_t1_with_track(&mut self, track: u64) -> anyhow::Result<()> {
-        self.set_property::<property::LavfiComplex>(format!("[aid1] [aid{track}] amix [ao]"))?;
-        Ok(())
+    pub fn set_lavfi_complex(&mut self, graph: String) -> anyhow::Result<()> {
+        self.set_property::<property::LavfiComplex>(graph)
     }
 
     pub(crate) fn switch_to_track(&mut self, id: u64) -> anyhow::Result<()> {
@@ -197,9 +355,18 @@ impl Bridge {
         Ok(())
     }
 
-    pub(crate) fn remove_track(&mut self, track_num: u64) -> anyhow::Result<()> {
-        self.write_command(AudioRemove(track_num))?;
-        Ok(())
+    /// Play the track `id` of the given kind
+    pub(crate) fn select_track(&mut self, kind: TrackKind, id: u64) -> anyhow::Result<()> {
+        match kind {
+            TrackKind::Audio => self.switch_to_track(id),
+            TrackKind::Video => self.set_property::<property::VideoId>(id),
+            TrackKind::Sub => self.set_property::<property::Sid>(id),
+        }
+    }
+
+    /// Remove an external track
+    pub(crate) fn remove_track(&mut self, kind: TrackKind, id: u64) -> anyhow::Result<()> {
+        self.write_command(TrackRemove(kind, id))
     }
 
     pub(crate) fn set_loop_file(&mut self, loop_file: bool) {
@@ -212,4 +379,8 @@ impl Bridge {
     pub fn playlist_next(&mut self) {
         let _ = self.write_command(command::PlaylistNext);
     }
+    /// Ask mpv to quit
+    pub fn quit(&mut self) -> anyhow::Result<()> {
+        self.write_command(command::Quit)
+    }
 }
@@ -1,5 +1,8 @@
 use {
-    super::property::{PropValue, Property},
+    super::{
+        TrackKind,
+        property::{PropValue, Property},
+    },
     serde::Serialize,
     std::marker::PhantomData,
 };
@@ -33,13 +36,29 @@ impl Command for AudioAdd<'_> {
     }
 }
 
-pub(super) struct AudioRemove(pub(super) u64);
+/// Change mpv's audio filter chain. The operation is `"add"` or `"remove"`.
+pub(super) struct Af<'a>(pub(super) &'static str, pub(super) &'a str);
 
-impl Command for AudioRemove {
+impl Command for Af<'_> {
+    type R = [serde_json::Value; 3];
+
+    fn json_values(&self) -> Self::R {
+        ["af".into(), self.0.into(), self.1.into()]
+    }
+}
+
+pub(super) struct TrackRemove(pub(super) TrackKind, pub(super) u64);
+
+impl Command for TrackRemove {
     type R = [serde_json::Value; 2];
 
     fn json_values(&self) -> Self::R {
-        ["audio-remove".into(), self.0.into()]
+        let cmd = match self.0 {
+            TrackKind::Audio => "audio-remove",
+            TrackKind::Video => "video-remove",
+            TrackKind::Sub => "sub-remove",
+        };
+        [cmd.into(), self.1.into()]
     }
 }
 
@@ -60,8 +79,19 @@ where
     }
 }
 
+/// Add to the value of a numeric property
+pub(super) struct AddProperty<P: Property>(pub(super) f64, pub(super) PhantomData<P>);
+
+impl<P: Property> Command for AddProperty<P> {
+    type R = [serde_json::Value; 3];
+    fn json_values(&self) -> Self::R {
+        ["add".into(), P::NAME.into(), self.0.into()]
+    }
+}
+
 pub(super) struct PlaylistPrev;
 pub(super) struct PlaylistNext;
+pub(super) struct Quit;
 
 impl Command for PlaylistPrev {
     type R = [&'static str; 1];
@@ -78,3 +108,11 @@ impl Command for PlaylistNext {
         ["playlist-next"]
     }
 }
+
+impl Command for Quit {
+    type R = [&'static str; 1];
+
+    fn json_values(&self) -> Self::R {
+        ["quit"]
+    }
+}
@@ -0,0 +1,149 @@
+//! In-process stand-in for mpv's JSON IPC server, for testing
+
+use {
+    interprocess::local_socket::{
+        GenericFilePath, ListenerOptions, ToFsName as _,
+        traits::{Listener as _, Stream as _},
+    },
+    serde_json::{Value, json},
+    std::{
+        io::{BufRead as _, BufReader, Write as _},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, AtomicU32, Ordering},
+            mpsc::Sender,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+/// Listens on a socket like `mpv --input-ipc-server` does.
+///
+/// Records the commands it receives, and sends whatever events the test tells it to.
+pub struct FakeMpv {
+    socket_path: String,
+    received: Arc<Mutex<Vec<Value>>>,
+    send: Sender<Vec<u8>>,
+    exited: Arc<AtomicBool>,
+}
+
+impl FakeMpv {
+    /// Start listening. The client can connect as soon as this returns.
+    pub fn start() -> Self {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let socket_path = format!(
+            "/tmp/mpvfrog-fake-{}-{}.sock",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let _ = std::fs::remove_file(&socket_path);
+        let name = socket_path
+            .as_str()
+            .to_fs_name::<GenericFilePath>()
+            .unwrap();
+        let listener = ListenerOptions::new().name(name).create_sync().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let exited = Arc::new(AtomicBool::new(false));
+        let (send, recv) = std::sync::mpsc::channel::<Vec<u8>>();
+        let reply_send = send.clone();
+        let thread_received = received.clone();
+        let thread_exited = exited.clone();
+        std::thread::spawn(move || {
+            let Ok(stream) = listener.accept() else {
+                return;
+            };
+            let (recv_half, mut send_half) = stream.split();
+            std::thread::spawn(move || {
+                for line in BufReader::new(recv_half).lines() {
+                    let Ok(line) = line else {
+                        return;
+                    };
+                    let command: Value = serde_json::from_str(&line).unwrap();
+                    if command["command"][0] == "quit" {
+                        thread_exited.store(true, Ordering::Relaxed);
+                    }
+                    // Real mpv replies to every command. The reply is queued while holding
+                    // the lock, so once a test sees a command, the reply is already queued
+                    // ahead of anything the test sends.
+                    let mut received = thread_received.lock().unwrap();
+                    received.push(command);
+                    let reply = json!({"data": null, "request_id": 0, "error": "success"});
+                    let _ = reply_send.send(line_bytes(&reply));
+                }
+            });
+            for bytes in recv {
+                if send_half.write_all(&bytes).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            socket_path,
+            received,
+            send,
+            exited,
+        }
+    }
+    pub fn socket_path(&self) -> &str {
+        &self.socket_path
+    }
+    /// The commands received so far, in order
+    pub fn received(&self) -> Vec<Value> {
+        self.received.lock().unwrap().clone()
+    }
+    /// Whether a command with this name has been received
+    pub fn received_command(&self, name: &str) -> bool {
+        self.received()
+            .iter()
+            .any(|command| command["command"][0] == name)
+    }
+    /// Send raw bytes to the client, to be able to send partial or malformed messages
+    pub fn send_raw(&self, bytes: &[u8]) {
+        self.send.send(bytes.to_vec()).unwrap();
+    }
+    pub fn send_message(&self, msg: &Value) {
+        self.send.send(line_bytes(msg)).unwrap();
+    }
+    pub fn property_change(&self, name: &str, data: Value) {
+        self.send_message(
+            &json!({"event": "property-change", "id": 1, "name": name, "data": data}),
+        );
+    }
+    pub fn end_file(&self) {
+        self.send_message(&json!({"event": "end-file", "reason": "eof"}));
+    }
+    /// Pretend that mpv has exited, like it does after it's done playing
+    pub fn exit(&self) {
+        self.exited.store(true, Ordering::Relaxed);
+    }
+    /// Whether mpv has "exited", either from [`Self::exit`] or from a quit command
+    pub fn exited(&self) -> bool {
+        self.exited.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for FakeMpv {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+fn line_bytes(msg: &Value) -> Vec<u8> {
+    let mut bytes = serde_json::to_vec(msg).unwrap();
+    bytes.push(b'\n');
+    bytes
+}
+
+/// Keep checking `cond` until it holds. Panics if it takes too long.
+///
+/// `cond` can also do some work, like handling responses from the fake mpv.
+pub fn wait_until(mut cond: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !cond() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "Timed out waiting for condition"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
@@ -79,9 +79,23 @@ decl_properties! {
     AbLoopB, "ab-loop-b", Option<f64>;
     LavfiComplex, "lavfi-complex", String;
     Aid, "aid", u64;
+    // Like `Video`, but for picking a video track by id
+    VideoId, "vid", u64;
+    Sid, "sid", u64;
     LoopFile, "loop-file", Option<&'static str>;
-    TrackListCount, "track-list/count", u64;
+    TrackList, "track-list", Vec<super::TrackEntry>;
     PlaylistPos, "playlist-pos", u64;
     PlaylistCount, "playlist-count", u64;
     Seekable, "seekable", bool;
+    AudioDevice, "audio-device", String;
+    AudioDeviceList, "audio-device-list", Vec<super::AudioDeviceEntry>;
+    ReplayGain, "replaygain", String;
+    ReplayGainPreamp, "replaygain-preamp", f64;
+    ReplayGainClip, "replaygain-clip", bool;
+    AudioPitchCorrection, "audio-pitch-correction", bool;
+    Chapter, "chapter", u64;
+    ChapterList, "chapter-list", Vec<super::ChapterEntry>;
+    Metadata, "metadata", std::collections::BTreeMap<String, String>;
+    // The label is `audio_filter::LEVELS_LABEL`
+    LevelsMetadata, "af-metadata/mpvfrog-levels", serde_json::Value;
 }
@@ -0,0 +1,234 @@
+use {
+    super::{
+        Bridge, IpcEvent, SocketTransport, TrackKind,
+        fake_mpv::{FakeMpv, wait_until},
+    },
+    serde_json::json,
+};
+
+fn connect() -> (FakeMpv, Bridge) {
+    let mpv = FakeMpv::start();
+    let transport = SocketTransport::connect(mpv.socket_path()).unwrap();
+    let mut bridge = Bridge::new(Box::new(transport)).unwrap();
+    // Wait for the replies to the observe commands, so they don't interleave with
+    // whatever the test sends. Commands are handled in order, so it's enough to wait
+    // for one more command to arrive.
+    bridge.set_loop_file(false);
+    wait_until(|| {
+        mpv.received()
+            .last()
+            .is_some_and(|cmd| cmd["command"][1] == "loop-file")
+    });
+    (mpv, bridge)
+}
+
+fn handle_until(bridge: &mut Bridge, mut cond: impl FnMut(&Bridge) -> bool) {
+    wait_until(|| {
+        bridge.handle_responses().unwrap();
+        cond(bridge)
+    });
+}
+
+#[test]
+fn observes_properties_on_connect() {
+    let (mpv, _bridge) = connect();
+    assert!(
+        mpv.received()
+            .contains(&json!({"command": ["observe_property", 1, "volume"]}))
+    );
+}
+
+#[test]
+fn sends_commands() {
+    let (mpv, mut bridge) = connect();
+    let n_received = mpv.received().len();
+    bridge.set_volume(50).unwrap();
+    bridge.seek(12.5).unwrap();
+    wait_until(|| mpv.received().len() == n_received + 2);
+    assert_eq!(
+        mpv.received()[n_received..],
+        [
+            json!({"command": ["set_property", "volume", 50.0]}),
+            json!({"command": ["set_property", "time-pos", 12.5]}),
+        ]
+    );
+}
+
+#[test]
+fn property_changes() {
+    let (mpv, mut bridge) = connect();
+    mpv.property_change("volume", json!(42.0));
+    mpv.property_change("duration", json!(180.5));
+    mpv.property_change("ab-loop-a", json!(3.0));
+    handle_until(&mut bridge, |b| b.observed.ab_loop_a.is_some());
+    assert_eq!(bridge.observed.volume, 42);
+    assert_eq!(bridge.observed.duration, 180.5);
+    assert_eq!(bridge.observed.ab_loop_a, Some(3.0));
+    // mpv sends "no" when the loop point is unset
+    mpv.property_change("ab-loop-a", json!("no"));
+    handle_until(&mut bridge, |b| b.observed.ab_loop_a.is_none());
+}
+
+#[test]
+fn partial_lines() {
+    let (mpv, mut bridge) = connect();
+    mpv.send_raw(br#"{"event":"property-change","name":"volume","da"#);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    bridge.handle_responses().unwrap();
+    assert_eq!(bridge.observed.volume, 0);
+    mpv.send_raw(b"ta\":42}\n");
+    handle_until(&mut bridge, |b| b.observed.volume == 42);
+}
+
+#[test]
+fn partial_utf8() {
+    let (mpv, mut bridge) = connect();
+    let msg = r#"{"event":"property-change","name":"lavfi-complex","data":"[aid1] äö [ao]"}"#;
+    // Split in the middle of `ä`
+    let split = msg.find('ä').unwrap() + 1;
+    mpv.send_raw(&msg.as_bytes()[..split]);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    bridge.handle_responses().unwrap();
+    mpv.send_raw(&msg.as_bytes()[split..]);
+    mpv.send_raw(b"\n");
+    handle_until(&mut bridge, |b| !b.observed.lavfi_complex.is_empty());
+    assert_eq!(bridge.observed.lavfi_complex, "[aid1] äö [ao]");
+}
+
+#[test]
+fn several_messages_at_once() {
+    let (mpv, mut bridge) = connect();
+    mpv.send_raw(
+        b"{\"event\":\"property-change\",\"name\":\"volume\",\"data\":10}\n\
+          {\"event\":\"property-change\",\"name\":\"speed\",\"data\":1.5}\n",
+    );
+    handle_until(&mut bridge, |b| b.observed.speed == 1.5);
+    assert_eq!(bridge.observed.volume, 10);
+}
+
+#[test]
+fn unknown_and_malformed_messages() {
+    let (mpv, mut bridge) = connect();
+    mpv.send_message(&json!({"event": "client-message", "args": []}));
+    mpv.send_message(&json!({"event": 5}));
+    mpv.send_message(&json!({"event": "property-change", "data": 1}));
+    mpv.send_message(&json!({"event": "property-change", "name": "volume"}));
+    mpv.property_change("some-property-we-dont-observe", json!(true));
+    mpv.send_raw(b"this isn't json\n");
+    mpv.property_change("volume", json!(42));
+    handle_until(&mut bridge, |b| b.observed.volume == 42);
+}
+
+#[test]
+fn type_mismatch_keeps_old_value() {
+    let (mpv, mut bridge) = connect();
+    mpv.property_change("volume", json!(42));
+    handle_until(&mut bridge, |b| b.observed.volume == 42);
+    mpv.property_change("volume", json!("loud"));
+    mpv.property_change("seekable", json!(true));
+    handle_until(&mut bridge, |b| b.observed.seekable);
+    assert_eq!(bridge.observed.volume, 42);
+}
+
+#[test]
+fn end_file_is_queued() {
+    let (mpv, mut bridge) = connect();
+    mpv.end_file();
+    handle_until(&mut bridge, |b| !b.event_queue.is_empty());
+    assert!(matches!(
+        bridge.event_queue.pop_front(),
+        Some(IpcEvent::EndFile)
+    ));
+}
+
+#[test]
+fn quit() {
+    let (mpv, mut bridge) = connect();
+    bridge.quit().unwrap();
+    wait_until(|| mpv.exited());
+}
+
+#[test]
+fn audio_device_list() {
+    let (mpv, mut bridge) = connect();
+    mpv.property_change(
+        "audio-device-list",
+        json!([
+            {"name": "auto", "description": "Autoselect device"},
+            {"name": "pipewire", "description": "Default (pipewire)"},
+        ]),
+    );
+    handle_until(&mut bridge, |b| !b.event_queue.is_empty());
+    let Some(IpcEvent::AudioDevices(devices)) = bridge.event_queue.pop_front() else {
+        panic!("Expected audio device list");
+    };
+    let names: Vec<_> = devices.iter().map(|dev| dev.name.as_str()).collect();
+    assert_eq!(names, ["auto", "pipewire"]);
+}
+
+#[test]
+fn chapters() {
+    let (mpv, mut bridge) = connect();
+    mpv.property_change(
+        "chapter-list",
+        json!([
+            {"title": "Intro", "time": 0.0},
+            {"time": 93.5},
+        ]),
+    );
+    mpv.property_change("chapter", json!(-1));
+    handle_until(&mut bridge, |b| b.observed.chapters.len() == 2);
+    assert_eq!(bridge.observed.chapters[0].title, "Intro");
+    assert_eq!(bridge.observed.chapters[1].time, 93.5);
+    assert_eq!(bridge.observed.chapter, None);
+    mpv.property_change("chapter", json!(1));
+    handle_until(&mut bridge, |b| b.observed.chapter == Some(1));
+    bridge.add_chapter(-1).unwrap();
+    wait_until(|| {
+        mpv.received()
+            .contains(&json!({"command": ["add", "chapter", -1.0]}))
+    });
+}
+
+#[test]
+fn track_list() {
+    let (mpv, mut bridge) = connect();
+    mpv.property_change(
+        "track-list",
+        json!([
+            {"id": 1, "type": "audio", "lang": "jpn", "codec": "flac", "selected": true},
+            {"id": 2, "type": "audio", "title": "Commentary", "external": true},
+            {"id": 1, "type": "sub", "lang": "eng", "codec": "ass", "selected": false},
+        ]),
+    );
+    handle_until(&mut bridge, |b| b.observed.tracks.len() == 3);
+    let tracks = &bridge.observed.tracks;
+    assert!(tracks[0].kind == TrackKind::Audio && tracks[0].selected);
+    assert_eq!(tracks[1].title.as_deref(), Some("Commentary"));
+    assert!(tracks[2].kind == TrackKind::Sub && !tracks[2].external);
+    let n_received = mpv.received().len();
+    bridge.select_track(TrackKind::Sub, 1).unwrap();
+    bridge.remove_track(TrackKind::Audio, 2).unwrap();
+    wait_until(|| mpv.received().len() == n_received + 2);
+    assert_eq!(
+        mpv.received()[n_received..],
+        [
+            json!({"command": ["set_property", "sid", 1]}),
+            json!({"command": ["audio-remove", 2]}),
+        ]
+    );
+}
+
+#[test]
+fn levels() {
+    let (mpv, mut bridge) = connect();
+    mpv.property_change(
+        "af-metadata/mpvfrog-levels",
+        json!({
+            "lavfi.astats.1.RMS_level": "-18",
+            "lavfi.astats.1.Peak_level": "-6",
+        }),
+    );
+    handle_until(&mut bridge, |b| !b.observed.levels.is_empty());
+    assert_eq!(bridge.observed.levels[0].rms, -18.0);
+}
@@ -12,16 +12,29 @@
 // Annoying lints
 #![allow(clippy::collapsible_if)]
 
-use {clap::Parser, existing_instance::Msg, std::path::PathBuf};
+use {
+    clap::Parser,
+    existing_instance::Msg,
+    remote::{RemoteCmd, Reply},
+    std::{path::PathBuf, process::ExitCode},
+};
 
 mod app;
+mod audio_filter;
 mod config;
+mod control_socket;
+mod cover_art;
+mod cue;
 mod ipc;
 mod mpv_handler;
 mod rect_math;
+mod remote;
 mod runner;
+mod template;
 mod time_fmt;
+mod waveform;
 mod util {
+    pub mod cache;
     pub mod egui_ext;
     pub mod result_ext;
     pub mod str_ext;
@@ -30,6 +43,7 @@ mod util {
 const APP_LABEL: &str = "🐸 mpvfrog";
 
 #[derive(clap::Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
     /// Path to file or directory
     ///
@@ -37,10 +51,13 @@ struct Args {
     ///
     /// - If it's a file, mpvfrog will set music directory to parent, and play the file
     path: Option<PathBuf>,
+    /// Control the running instance
+    #[command(subcommand)]
+    remote: Option<RemoteCmd>,
 }
 
 /// Entry point
-fn main() {
+fn main() -> ExitCode {
     let mut args = Args::parse();
     if let Some(path) = &mut args.path {
         // Canonicalize the path argument, so we can get the parent even for relative paths
@@ -53,19 +70,23 @@ fn main() {
             }
         }
     }
+    if let Some(cmd) = args.remote.take() {
+        return send_remote_cmd(cmd);
+    }
     let listener = match existing_instance::establish_endpoint("mpvfrog-instance", true) {
         Ok(endpoint) => match endpoint {
             existing_instance::Endpoint::New(listener) => Some(listener),
             existing_instance::Endpoint::Existing(mut stream) => {
                 match &args.path {
+                    // Just the path, which older versions understand too
                     Some(path) => {
-                        stream.send(Msg::String(path.as_os_str().to_str().unwrap().to_owned()));
+                        stream.send(Msg::String(path.to_string_lossy().into_owned()));
                     }
                     None => {
                         stream.send(Msg::Nudge);
                     }
                 }
-                return;
+                return ExitCode::SUCCESS;
             }
         },
         Err(e) => {
@@ -74,4 +95,45 @@ fn main() {
         }
     };
     runner::run(700, 500, "mpvfrog", listener, args);
+    ExitCode::SUCCESS
+}
+
+/// Send a command to the running instance, and print its reply
+fn send_remote_cmd(mut cmd: RemoteCmd) -> ExitCode {
+    if let Err(e) = cmd.canonicalize_paths() {
+        eprintln!("Invalid path: {e}");
+        return ExitCode::FAILURE;
+    }
+    let mut stream = match existing_instance::establish_endpoint("mpvfrog-instance", true) {
+        Ok(existing_instance::Endpoint::Existing(stream)) => stream,
+        Ok(existing_instance::Endpoint::New(_)) => {
+            eprintln!("mpvfrog is not running");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to mpvfrog: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    stream.send(Msg::String(serde_json::to_string(&cmd).unwrap()));
+    let reply = match stream.recv() {
+        Some(Msg::String(reply)) => reply,
+        _ => {
+            eprintln!("No reply from mpvfrog");
+            return ExitCode::FAILURE;
+        }
+    };
+    match serde_json::from_str::<Reply>(&reply) {
+        Ok(Ok(Some(data))) => println!("{data}"),
+        Ok(Ok(None)) => {}
+        Ok(Err(e)) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("Invalid reply from mpvfrog: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
 }
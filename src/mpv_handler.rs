@@ -1,9 +1,15 @@
 //! Handling of the spawned mpv process
 
+#[cfg(test)]
+pub mod fake;
+#[cfg(feature = "libmpv")]
+mod libmpv;
+mod process;
+
 use {
     crate::{
         app::ModalPopup,
-        config::ArgType,
+        config::{ArgType, BackendKind},
         ipc::{self, IpcEvent},
         logln,
         util::str_ext::trim_lines,
@@ -11,20 +17,59 @@ use {
     ansi_term_buf::Term,
     anyhow::Context,
     pty_process::blocking::{Command as PtyCommand, Pty},
+    rustix::process::{Pid, Signal, kill_process},
     std::{
         ffi::{OsStr, OsString},
-        io::{Read as _, Write as _},
+        io::Read as _,
         ops::ControlFlow,
-        process::{Child, Stdio},
-        sync::Arc,
-        time::Duration,
+        process::{Child, ChildStdout, ExitStatus, Stdio},
+        sync::{
+            Arc,
+            mpsc::{Receiver, TryRecvError},
+        },
+        time::{Duration, Instant},
     },
 };
 
-struct MpvHandlerInner {
-    child: PtyChildState,
-    demux: Option<PtyChildState>,
-    ipc_bridge: ipc::Bridge,
+/// A way of running mpv.
+///
+/// [`MpvHandler`] delegates the actual playback to a backend, so the rest of the
+/// application doesn't have to care how mpv is being run.
+pub trait Backend {
+    /// Start playing with the given mpv command line arguments.
+    ///
+    /// If there is a custom demuxer, mpv should read its output instead of a file.
+    fn play(
+        &mut self,
+        mpv_cmd: &str,
+        args: Vec<OsString>,
+        demux: Option<DemuxerChild>,
+    ) -> anyhow::Result<()>;
+    /// Stop playing. This shouldn't block waiting for mpv to exit.
+    fn stop(&mut self) -> anyhow::Result<()>;
+    /// Handle responses from mpv, and feed any new output into `output`.
+    ///
+    /// Returns [`ControlFlow::Break`] if playback ended abnormally, or if nothing is playing.
+    fn update(&mut self, output: &mut Output, modal: &mut ModalPopup) -> ControlFlow<()>;
+    /// Whether mpv is playing (or in the process of starting up)
+    fn active(&self) -> bool;
+    /// Whether mpv has been started, but isn't ready to be controlled yet
+    fn starting(&self) -> bool;
+    /// Observed properties, events, and commands
+    fn bridge(&self) -> Option<&ipc::Bridge>;
+    fn bridge_mut(&mut self) -> Option<&mut ipc::Bridge>;
+    fn demuxer_active(&self) -> bool;
+    /// Send terminal input to mpv or the demuxer
+    fn send_input(&mut self, target: &ActivePtyInput, s: &str);
+}
+
+/// Terminal output of mpv and the custom demuxer
+pub struct Output {
+    pub mpv_term: Term,
+    pub demux_term: Term,
+    /// Name of the demuxer command. This should be synchronized with the term, so the tab
+    /// shows the same name as the command that produced the term output.
+    pub demux_cmd_name: String,
 }
 
 enum PtyChildMsg {
@@ -33,7 +78,7 @@ enum PtyChildMsg {
 
 struct PtyChildState {
     child: Child,
-    recv: std::sync::mpsc::Receiver<PtyChildMsg>,
+    recv: Receiver<PtyChildMsg>,
     pty: Arc<Pty>,
 }
 
@@ -68,13 +113,113 @@ fn spawn_pty_child(pty: Arc<Pty>, child: Child) -> PtyChildState {
     }
 }
 
+/// Feed everything the pty reader thread has sent so far into `term`.
+///
+/// Returns `false` if the reader thread has hung up.
+fn drain_pty_output(recv: &Receiver<PtyChildMsg>, term: &mut Term) -> bool {
+    loop {
+        match recv.try_recv() {
+            Ok(PtyChildMsg::Read { buf, len }) => term.feed(&buf[..len]),
+            Err(TryRecvError::Empty) => return true,
+            Err(TryRecvError::Disconnected) => return false,
+        }
+    }
+}
+
+/// How long mpv (or the demuxer) gets to exit on its own before we send SIGTERM
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a child gets to exit after SIGTERM before we send SIGKILL
+const TERM_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn send_sigterm(child: &Child) -> std::io::Result<()> {
+    kill_process(Pid::from_child(child), Signal::TERM)?;
+    Ok(())
+}
+
+/// Poll `child` until it exits, or `timeout` elapses
+fn wait_timeout(child: &mut Child, timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Wait for `child` to exit, escalating to SIGTERM, and then SIGKILL if it doesn't
+fn terminate_child(child: &mut Child, name: &str) -> anyhow::Result<ExitStatus> {
+    if let Some(status) = wait_timeout(child, QUIT_TIMEOUT)? {
+        return Ok(status);
+    }
+    logln!("{name} didn't exit in time, sending SIGTERM");
+    send_sigterm(child).context("Failed to send SIGTERM")?;
+    if let Some(status) = wait_timeout(child, TERM_TIMEOUT)? {
+        return Ok(status);
+    }
+    logln!("{name} didn't exit after SIGTERM, killing it");
+    child.kill().context("Failed to kill")?;
+    Ok(child.wait()?)
+}
+
+/// A spawned custom demuxer, whose output hasn't been hooked up to mpv yet
+pub struct DemuxerChild {
+    state: PtyChildState,
+    stdout: ChildStdout,
+}
+
+fn spawn_demuxer(demuxer: CustomDemuxer) -> anyhow::Result<DemuxerChild> {
+    logln!("Demuxer: {}, args: {:?}", demuxer.cmd, demuxer.args);
+    let (pty, pts) = pty_process::blocking::open()?;
+    let mut child = PtyCommand::new(demuxer.cmd)
+        .args(demuxer.args)
+        .stdout(Stdio::piped())
+        .spawn(pts)
+        .context("Failed to spawn demuxer")?;
+    let stdout = child.stdout.take().context("Demuxer has no stdout")?;
+    Ok(DemuxerChild {
+        state: spawn_pty_child(Arc::new(pty), child),
+        stdout,
+    })
+}
+
+/// Wait for mpv and the demuxer to exit on a background thread, so we don't
+/// block the ui while they are shutting down.
+///
+/// Children that don't exit in time are terminated.
+fn reap_in_background(
+    mpv: Option<PtyChildState>,
+    demux: Option<PtyChildState>,
+    socket_path: Option<String>,
+) {
+    std::thread::spawn(move || {
+        if let Some(mut mpv) = mpv {
+            match terminate_child(&mut mpv.child, "mpv") {
+                Ok(status) => logln!("mpv exited with status: {status}"),
+                Err(e) => logln!("Error terminating mpv: {e:?}"),
+            }
+        }
+        // The demuxer usually exits on its own once mpv stops reading its output
+        if let Some(mut demux) = demux {
+            match terminate_child(&mut demux.child, "Demuxer") {
+                Ok(status) => logln!("Demuxer exited with status: {status}"),
+                Err(e) => logln!("Error terminating demuxer: {e:?}"),
+            }
+        }
+        // mpv normally cleans up after itself, but not if it was killed
+        if let Some(path) = socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    });
+}
+
 pub struct MpvHandler {
-    pub mpv_term: Term,
-    pub demux_term: Term,
-    /// Name of the demuxer command. This should be synchronized with the term, so the tab
-    /// shows the same name as the command that produced the term output.
-    pub demux_cmd_name: String,
-    inner: Option<MpvHandlerInner>,
+    pub output: Output,
+    backend: Box<dyn Backend>,
+    backend_kind: BackendKind,
     pub active_pty_input: ActivePtyInput,
 }
 
@@ -88,226 +233,127 @@ pub struct CustomDemuxer {
     args: Vec<OsString>,
 }
 
+fn make_backend(kind: BackendKind) -> Box<dyn Backend> {
+    match kind {
+        BackendKind::Process => Box::<process::ProcessBackend>::default(),
+        #[cfg(feature = "libmpv")]
+        BackendKind::Libmpv => Box::<libmpv::LibmpvBackend>::default(),
+        #[cfg(not(feature = "libmpv"))]
+        BackendKind::Libmpv => {
+            logln!("mpvfrog was built without libmpv support, using the process backend");
+            Box::<process::ProcessBackend>::default()
+        }
+    }
+}
+
 impl MpvHandler {
+    /// Create a handler that uses `backend`, as long as the backend kind isn't changed
+    #[cfg(test)]
+    pub(crate) fn with_backend(backend: Box<dyn Backend>) -> Self {
+        Self {
+            backend,
+            ..Default::default()
+        }
+    }
+    /// Switch to a different backend. This stops the current playback, if needed.
+    pub fn set_backend(&mut self, kind: BackendKind) {
+        if kind == self.backend_kind {
+            return;
+        }
+        if let Err(e) = self.stop_music() {
+            logln!("Error stopping mpv: {e:?}");
+        }
+        self.backend = make_backend(kind);
+        self.backend_kind = kind;
+    }
     pub fn play_music<'a>(
         &mut self,
         mpv_cmd: &str,
         mpv_args: impl IntoIterator<Item = &'a OsStr>,
         custom_demuxer: Option<CustomDemuxer>,
     ) -> anyhow::Result<()> {
-        self.stop_music();
-        self.mpv_term.reset();
-        self.demux_term.reset();
-        self.demux_cmd_name.clear();
-        let (pty, pts) = pty_process::blocking::open()?;
-        let pty = Arc::new(pty);
-        let mut mpv_command = PtyCommand::new(mpv_cmd);
-        let (demuxer_pty, demux_pts) = pty_process::blocking::open()?;
-        let demuxer_pty = Arc::new(demuxer_pty);
-        mpv_command = mpv_command.args(mpv_args);
-        let mut demux = None;
-        if let Some(demuxer) = custom_demuxer {
-            logln!("Demuxer: {}, args: {:?}", demuxer.cmd, demuxer.args);
-            self.demux_cmd_name = demuxer.cmd.clone();
-            let mut demux_child = PtyCommand::new(demuxer.cmd)
-                .args(demuxer.args)
-                .stdout(Stdio::piped())
-                .spawn(demux_pts)
-                .context("Failed to spawn demuxer")?;
-            mpv_command = mpv_command.stdin(demux_child.stdout.take().unwrap());
-            demux = Some(spawn_pty_child(demuxer_pty, demux_child));
+        if let Err(e) = self.stop_music() {
+            logln!("Error stopping previous mpv: {e:?}");
         }
-        let mut child = mpv_command.spawn(pts)?;
-        let attempts = 5;
-        let ipc_bridge = 'connect: {
-            for i in 0..attempts {
-                std::thread::sleep(Duration::from_millis(100));
-                match ipc::Bridge::connect() {
-                    Ok(bridge) => break 'connect bridge,
-                    Err(e) => {
-                        if let Some(status) = child.try_wait()? {
-                            let mut stderr = Vec::new();
-                            let result = (&*pty).read_to_end(&mut stderr);
-                            if let Err(e) = result {
-                                logln!("Failed to read mpv pty: {e}");
-                            }
-                            let mut term = Term::new(80);
-                            term.feed(&stderr);
-                            let stderr = trim_lines(term.contents_to_string());
-                            anyhow::bail!("mpv exited with {status}.\nStderr:\n{stderr}");
-                        }
-                        logln!("mpv connection attempt #{i}: {e}");
-                    }
-                }
+        self.output.mpv_term.reset();
+        self.output.demux_term.reset();
+        self.output.demux_cmd_name.clear();
+        let demux = match custom_demuxer {
+            Some(demuxer) => {
+                self.output.demux_cmd_name = demuxer.cmd.clone();
+                Some(spawn_demuxer(demuxer)?)
             }
-            anyhow::bail!("Failed connect to mpv");
+            None => None,
         };
-        let mpv_child = spawn_pty_child(pty, child);
-        self.inner = Some(MpvHandlerInner {
-            child: mpv_child,
-            demux,
-            ipc_bridge,
-        });
-        Ok(())
+        let args = mpv_args.into_iter().map(OsStr::to_owned).collect();
+        self.backend.play(mpv_cmd, args, demux)
     }
-    pub fn stop_music(&mut self) {
-        let Some(inner) = &mut self.inner else { return };
-        (&*inner.child.pty).write_all(b"q").unwrap();
-        inner.child.child.wait().unwrap();
-        'wait_demuxer: {
-            if let Some(mut demux) = inner.demux.take() {
-                for i in 0..5 {
-                    logln!("Wait for demuxer to exit (attempt {i})");
-                    if let Some(status) = demux.child.try_wait().unwrap() {
-                        logln!("Demuxer exited with status: {status}");
-                        break 'wait_demuxer;
-                    }
-                    std::thread::sleep(Duration::from_millis(100));
-                }
-                demux.child.kill().unwrap();
-                logln!("Killed demuxer");
-            }
-        }
-        self.inner = None;
+    /// Ask mpv (and the demuxer, if any) to quit.
+    ///
+    /// This doesn't wait for them to exit, the waiting happens on a background thread.
+    pub fn stop_music(&mut self) -> anyhow::Result<()> {
+        self.backend.stop()
     }
     pub fn update(&mut self, modal: &mut ModalPopup) -> ControlFlow<()> {
-        let Some(inner) = &mut self.inner else {
-            return ControlFlow::Break(());
-        };
-        if let Err(e) = inner.ipc_bridge.handle_responses() {
-            modal.warn("Mpv IPC error", e);
-        }
-        match inner.child.child.try_wait() {
-            Ok(None) => {}
-            Ok(Some(status)) => {
-                if !status.success() {
-                    let mut remaining_data = Vec::new();
-                    let result = (&*inner.child.pty).read_to_end(&mut remaining_data);
-                    if let Err(e) = result {
-                        logln!("Failed to read mpv pty: {e}");
-                    }
-                    self.mpv_term.feed(&remaining_data);
-                    let stderr = trim_lines(self.mpv_term.contents_to_string());
-                    modal.error(
-                        "Abnormal mpv termination",
-                        format!("Mpv exited with status {status}\nStderr:\n{stderr}"),
-                    );
-                    self.inner = None;
-                    return ControlFlow::Break(());
-                }
-            }
-            Err(e) => {
-                modal.error(
-                    "Abnormal mpv termination",
-                    format!("Error waiting on mpv: {e}"),
-                );
-                self.inner = None;
-                return ControlFlow::Break(());
-            }
-        }
-        loop {
-            match inner.child.recv.try_recv() {
-                Ok(msg) => match msg {
-                    PtyChildMsg::Read { buf, len } => {
-                        self.mpv_term.feed(&buf[..len]);
-                    }
-                },
-                Err(e) => match e {
-                    std::sync::mpsc::TryRecvError::Empty => break,
-                    std::sync::mpsc::TryRecvError::Disconnected => {
-                        logln!("Mpv channel disconnected!");
-                        self.inner = None;
-                        return ControlFlow::Continue(());
-                    }
-                },
-            }
-        }
-        if let Some(demux) = &inner.demux {
-            loop {
-                match demux.recv.try_recv() {
-                    Ok(msg) => match msg {
-                        PtyChildMsg::Read { buf, len } => {
-                            self.demux_term.feed(&buf[..len]);
-                        }
-                    },
-                    Err(e) => match e {
-                        std::sync::mpsc::TryRecvError::Empty => break,
-                        std::sync::mpsc::TryRecvError::Disconnected => {
-                            logln!("Demux channel disconnected!");
-                            break;
-                        }
-                    },
-                }
-            }
-        }
-        ControlFlow::Continue(())
+        self.backend.update(&mut self.output, modal)
     }
 
     pub fn send_input(&mut self, s: &str) {
-        let Some(inner) = &mut self.inner else { return };
-        let mut pty = match self.active_pty_input {
-            ActivePtyInput::Mpv => &*inner.child.pty,
-            ActivePtyInput::Demuxer => match &inner.demux {
-                Some(demux) => &demux.pty,
-                None => {
-                    logln!("Trying to write to demux while no demux");
-                    return;
-                }
-            },
-        };
-        pty.write_all(s.as_bytes()).unwrap();
+        self.backend.send_input(&self.active_pty_input, s);
     }
 
+    /// Whether mpv is running (or in the process of starting up)
     pub fn active(&self) -> bool {
-        self.inner.is_some()
+        self.backend.active()
+    }
+
+    /// Whether mpv has been started, but we can't control it yet
+    pub fn starting(&self) -> bool {
+        self.backend.starting()
     }
 
     pub fn paused(&self) -> bool {
-        match &self.inner {
-            Some(inner) => inner.ipc_bridge.observed.paused,
+        match self.backend.bridge() {
+            Some(bridge) => bridge.observed.paused,
             None => true,
         }
     }
     pub fn mpv_output(&self) -> String {
-        trim_lines(self.mpv_term.contents_to_string())
+        trim_lines(self.output.mpv_term.contents_to_string())
     }
     pub fn ab_loop(&self) -> Option<(Option<f64>, Option<f64>)> {
-        self.inner.as_ref().map(|inner| {
-            (
-                inner.ipc_bridge.observed.ab_loop_a,
-                inner.ipc_bridge.observed.ab_loop_b,
-            )
-        })
+        self.backend
+            .bridge()
+            .map(|bridge| (bridge.observed.ab_loop_a, bridge.observed.ab_loop_b))
     }
 
     pub(crate) fn time_info(&self) -> Option<TimeInfo> {
-        self.inner.as_ref().map(|inner| TimeInfo {
-            pos: inner.ipc_bridge.observed.time_pos,
-            duration: inner.ipc_bridge.observed.duration,
+        self.backend.bridge().map(|bridge| TimeInfo {
+            pos: bridge.observed.time_pos,
+            duration: bridge.observed.duration,
         })
     }
 
+    /// The properties observed from mpv, if there is an IPC bridge
+    pub(crate) fn observed(&self) -> Option<&ipc::Properties> {
+        self.backend.bridge().map(|bridge| &bridge.observed)
+    }
+
     pub(crate) fn poll_event(&mut self) -> Option<IpcEvent> {
-        match &mut self.inner {
-            Some(inner) => inner.ipc_bridge.event_queue.pop_front(),
-            None => None,
-        }
+        self.backend
+            .bridge_mut()
+            .and_then(|bridge| bridge.event_queue.pop_front())
     }
 
     pub(crate) fn demuxer_active(&self) -> bool {
-        self.inner
-            .as_ref()
-            .is_some_and(|inner| inner.demux.is_some())
+        self.backend.demuxer_active()
     }
     /// Send a command to the IPC bridge, if it exists
     pub(crate) fn ipc<'br, T, F>(&'br mut self, fun: F) -> Option<T>
     where
         F: FnOnce(&'br mut ipc::Bridge) -> T,
     {
-        match &mut self.inner {
-            Some(inner) => Some(fun(&mut inner.ipc_bridge)),
-            None => None,
-        }
+        self.backend.bridge_mut().map(fun)
     }
 }
 
@@ -319,10 +365,13 @@ pub struct TimeInfo {
 impl Default for MpvHandler {
     fn default() -> Self {
         Self {
-            mpv_term: Term::new(80),
-            demux_term: Term::new(80),
-            demux_cmd_name: String::new(),
-            inner: None,
+            output: Output {
+                mpv_term: Term::new(80),
+                demux_term: Term::new(80),
+                demux_cmd_name: String::new(),
+            },
+            backend: Box::<process::ProcessBackend>::default(),
+            backend_kind: BackendKind::Process,
             active_pty_input: ActivePtyInput::Mpv,
         }
     }
@@ -0,0 +1,92 @@
+//! Backend that "plays" through a [`FakeMpv`], for testing
+
+use {
+    super::{ActivePtyInput, Backend, DemuxerChild, Output},
+    crate::{
+        app::ModalPopup,
+        ipc::{Bridge, SocketTransport, fake_mpv::FakeMpv},
+    },
+    std::{cell::RefCell, ffi::OsString, ops::ControlFlow, rc::Rc},
+};
+
+/// An mpv that the fake backend has started
+pub struct SpawnedMpv {
+    pub args: Vec<OsString>,
+    pub mpv: Rc<FakeMpv>,
+}
+
+/// Every mpv started by a [`FakeBackend`], so tests can inspect and control them
+pub type Spawned = Rc<RefCell<Vec<SpawnedMpv>>>;
+
+pub struct FakeBackend {
+    running: Option<(Rc<FakeMpv>, Bridge)>,
+    spawned: Spawned,
+}
+
+impl FakeBackend {
+    pub fn new() -> (Self, Spawned) {
+        let spawned = Spawned::default();
+        let this = Self {
+            running: None,
+            spawned: spawned.clone(),
+        };
+        (this, spawned)
+    }
+}
+
+impl Backend for FakeBackend {
+    fn play(
+        &mut self,
+        _mpv_cmd: &str,
+        args: Vec<OsString>,
+        demux: Option<DemuxerChild>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            demux.is_none(),
+            "The fake backend doesn't support custom demuxers"
+        );
+        let mpv = Rc::new(FakeMpv::start());
+        let transport = SocketTransport::connect(mpv.socket_path())?;
+        let bridge = Bridge::new(Box::new(transport))?;
+        self.spawned.borrow_mut().push(SpawnedMpv {
+            args,
+            mpv: mpv.clone(),
+        });
+        self.running = Some((mpv, bridge));
+        Ok(())
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        match self.running.take() {
+            Some((_mpv, mut bridge)) => bridge.quit(),
+            None => Ok(()),
+        }
+    }
+    fn update(&mut self, _output: &mut Output, modal: &mut ModalPopup) -> ControlFlow<()> {
+        let Some((mpv, bridge)) = &mut self.running else {
+            return ControlFlow::Break(());
+        };
+        if let Err(e) = bridge.handle_responses() {
+            modal.warn("Mpv IPC error", e);
+        }
+        if mpv.exited() {
+            self.running = None;
+        }
+        ControlFlow::Continue(())
+    }
+    fn active(&self) -> bool {
+        self.running.is_some()
+    }
+    fn starting(&self) -> bool {
+        false
+    }
+    fn bridge(&self) -> Option<&Bridge> {
+        self.running.as_ref().map(|(_, bridge)| bridge)
+    }
+    fn bridge_mut(&mut self) -> Option<&mut Bridge> {
+        self.running.as_mut().map(|(_, bridge)| bridge)
+    }
+    fn demuxer_active(&self) -> bool {
+        false
+    }
+    fn send_input(&mut self, _target: &ActivePtyInput, _s: &str) {}
+}
@@ -0,0 +1,280 @@
+//! Backend that runs mpv in-process through libmpv
+//!
+//! Properties are observed directly through libmpv's event loop, rather than over a socket.
+
+use {
+    super::{
+        ActivePtyInput, Backend, DemuxerChild, Output, PtyChildState, drain_pty_output,
+        reap_in_background,
+    },
+    crate::{
+        app::ModalPopup,
+        ipc::{Bridge, Transport},
+        logln,
+    },
+    libmpv2::{
+        Format, Mpv,
+        events::{Event, EventContext, PropertyData},
+        mpv_node::{MpvNode, MpvNodeValue},
+    },
+    serde_json::{Value, json},
+    std::{
+        cell::Cell, ffi::OsString, io::Write as _, ops::ControlFlow, os::fd::AsRawFd as _,
+        process::ChildStdout, rc::Rc,
+    },
+};
+
+#[derive(Default)]
+pub struct LibmpvBackend {
+    bridge: Option<Bridge>,
+    /// Set by the transport when mpv has nothing more to play
+    finished: Rc<Cell<bool>>,
+    demux: Option<PtyChildState>,
+    /// The demuxer's output, which mpv reads through `fd://`
+    demux_stdout: Option<ChildStdout>,
+}
+
+struct LibmpvTransport {
+    ev_ctx: EventContext,
+    mpv: Mpv,
+    /// Whether a file has been loaded yet, so we can tell the initial idle state
+    /// apart from having finished playing
+    loaded: bool,
+    finished: Rc<Cell<bool>>,
+}
+
+fn libmpv_err(e: libmpv2::Error) -> anyhow::Error {
+    anyhow::anyhow!("libmpv error: {e:?}")
+}
+
+impl Backend for LibmpvBackend {
+    fn play(
+        &mut self,
+        _mpv_cmd: &str,
+        args: Vec<OsString>,
+        demux: Option<DemuxerChild>,
+    ) -> anyhow::Result<()> {
+        // Options are passed as properties, and whatever isn't an option is the file to play
+        let mut options = Vec::new();
+        let mut file = None;
+        for arg in &args {
+            let arg = arg.to_string_lossy().into_owned();
+            match arg.strip_prefix("--") {
+                Some(opt) => match opt.split_once('=') {
+                    Some((name, value)) => match name.strip_suffix("-add") {
+                        // Appending to a list option isn't a property, so add to the list ourselves
+                        Some(list) => match options.iter_mut().find(|(option, _)| option == list) {
+                            Some((_, values)) => {
+                                values.push(',');
+                                values.push_str(value);
+                            }
+                            None => options.push((list.to_owned(), value.to_owned())),
+                        },
+                        None => options.push((name.to_owned(), value.to_owned())),
+                    },
+                    None => match opt.strip_prefix("no-") {
+                        Some(name) => options.push((name.to_owned(), "no".to_owned())),
+                        None => options.push((opt.to_owned(), "yes".to_owned())),
+                    },
+                },
+                None => file = Some(arg),
+            }
+        }
+        if let Some(demux) = demux {
+            file = Some(format!("fd://{}", demux.stdout.as_raw_fd()));
+            self.demux = Some(demux.state);
+            self.demux_stdout = Some(demux.stdout);
+        }
+        let Some(file) = file else {
+            anyhow::bail!("Nothing to play");
+        };
+        let mpv = Mpv::with_initializer(|init| {
+            // Behave like the mpv command line player as much as possible
+            init.set_property("config", "yes")?;
+            init.set_property("idle", "yes")?;
+            for (name, value) in &options {
+                if let Err(e) = init.set_property(name, value.as_str()) {
+                    logln!("Failed to set libmpv option {name}={value}: {e:?}");
+                }
+            }
+            Ok(())
+        })
+        .map_err(libmpv_err)?;
+        let mut ev_ctx = EventContext::new(mpv.ctx);
+        ev_ctx
+            .observe_property("idle-active", Format::Flag, 0)
+            .map_err(libmpv_err)?;
+        mpv.command("loadfile", &[&file]).map_err(libmpv_err)?;
+        self.finished = Rc::default();
+        let transport = LibmpvTransport {
+            ev_ctx,
+            mpv,
+            loaded: false,
+            finished: self.finished.clone(),
+        };
+        self.bridge = Some(Bridge::new(Box::new(transport))?);
+        Ok(())
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        if let Some(mut bridge) = self.bridge.take() {
+            result = bridge.quit();
+        }
+        self.demux_stdout = None;
+        reap_in_background(None, self.demux.take(), None);
+        result
+    }
+    fn update(&mut self, output: &mut Output, modal: &mut ModalPopup) -> ControlFlow<()> {
+        let Some(bridge) = &mut self.bridge else {
+            return ControlFlow::Break(());
+        };
+        if let Err(e) = bridge.handle_responses() {
+            modal.warn("libmpv error", e);
+        }
+        if let Some(demux) = &self.demux
+            && !drain_pty_output(&demux.recv, &mut output.demux_term)
+        {
+            logln!("Demux channel disconnected!");
+        }
+        if self.finished.get() {
+            self.bridge = None;
+            self.demux_stdout = None;
+            reap_in_background(None, self.demux.take(), None);
+        }
+        ControlFlow::Continue(())
+    }
+    fn active(&self) -> bool {
+        self.bridge.is_some()
+    }
+    fn starting(&self) -> bool {
+        false
+    }
+    fn bridge(&self) -> Option<&Bridge> {
+        self.bridge.as_ref()
+    }
+    fn bridge_mut(&mut self) -> Option<&mut Bridge> {
+        self.bridge.as_mut()
+    }
+    fn demuxer_active(&self) -> bool {
+        self.demux.is_some()
+    }
+    fn send_input(&mut self, target: &ActivePtyInput, s: &str) {
+        match target {
+            ActivePtyInput::Mpv => logln!("libmpv doesn't have a terminal to send input to"),
+            ActivePtyInput::Demuxer => {
+                if let Some(demux) = &self.demux
+                    && let Err(e) = (&*demux.pty).write_all(s.as_bytes())
+                {
+                    logln!("Failed to write to pty: {e}");
+                }
+            }
+        }
+    }
+}
+
+impl Transport for LibmpvTransport {
+    fn send(&mut self, command: &Value) -> anyhow::Result<()> {
+        let Some(args) = command.get("command").and_then(Value::as_array) else {
+            anyhow::bail!("Malformed command: {command}");
+        };
+        let Some((name, args)) = args.split_first() else {
+            anyhow::bail!("Empty command");
+        };
+        match (name.as_str(), args) {
+            (Some("observe_property"), [_id, name]) => {
+                let name = name.as_str().unwrap_or_default();
+                self.ev_ctx
+                    .observe_property(name, Format::Node, 1)
+                    .map_err(libmpv_err)
+            }
+            (Some("set_property"), [name, value]) => {
+                let name = name.as_str().unwrap_or_default();
+                let result = match value {
+                    Value::Bool(flag) => self.mpv.set_property(name, *flag),
+                    Value::Number(num) => match num.as_i64() {
+                        Some(num) => self.mpv.set_property(name, num),
+                        None => self
+                            .mpv
+                            .set_property(name, num.as_f64().unwrap_or_default()),
+                    },
+                    Value::String(string) => self.mpv.set_property(name, string.as_str()),
+                    other => self.mpv.set_property(name, other.to_string()),
+                };
+                result.map_err(libmpv_err)
+            }
+            (Some(name), args) => {
+                let args: Vec<String> = args.iter().map(json_to_arg).collect();
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                self.mpv.command(name, &args).map_err(libmpv_err)
+            }
+            (None, _) => anyhow::bail!("Malformed command: {command}"),
+        }
+    }
+    fn recv(&mut self) -> anyhow::Result<Option<Value>> {
+        loop {
+            let Some(event) = self.ev_ctx.wait_event(0.0) else {
+                return Ok(None);
+            };
+            match event {
+                Ok(Event::PropertyChange {
+                    name: "idle-active",
+                    change: PropertyData::Flag(idle),
+                    ..
+                }) => {
+                    if idle && self.loaded {
+                        self.finished.set(true);
+                    }
+                }
+                Ok(Event::PropertyChange { name, change, .. }) => {
+                    return Ok(Some(json!({
+                        "event": "property-change",
+                        "name": name,
+                        "data": prop_data_to_json(&change),
+                    })));
+                }
+                Ok(Event::FileLoaded) => self.loaded = true,
+                Ok(Event::EndFile(_)) => return Ok(Some(json!({"event": "end-file"}))),
+                Ok(Event::Shutdown) => {
+                    self.finished.set(true);
+                    return Ok(None);
+                }
+                Ok(_) => {}
+                Err(e) => logln!("libmpv event error: {e:?}"),
+            }
+        }
+    }
+}
+
+/// Command arguments are all strings for libmpv
+fn json_to_arg(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        Value::Bool(flag) => if *flag { "yes" } else { "no" }.to_owned(),
+        other => other.to_string(),
+    }
+}
+
+fn prop_data_to_json(data: &PropertyData) -> Value {
+    match data {
+        PropertyData::Str(string) | PropertyData::OsdStr(string) => (*string).into(),
+        PropertyData::Flag(flag) => (*flag).into(),
+        PropertyData::Int64(num) => (*num).into(),
+        PropertyData::Double(num) => (*num).into(),
+        PropertyData::Node(node) => node_to_json(node),
+    }
+}
+
+fn node_to_json(node: &MpvNode) -> Value {
+    match node.value() {
+        Ok(MpvNodeValue::String(string)) => string.into(),
+        Ok(MpvNodeValue::Flag(flag)) => flag.into(),
+        Ok(MpvNodeValue::Int64(num)) => num.into(),
+        Ok(MpvNodeValue::Double(num)) => num.into(),
+        Ok(MpvNodeValue::Array(array)) => array.map(|node| node_to_json(&node)).collect(),
+        Ok(MpvNodeValue::Map(map)) => Value::Object(
+            map.map(|(key, node)| (key.to_string(), node_to_json(&node)))
+                .collect(),
+        ),
+        Ok(MpvNodeValue::None) | Err(_) => Value::Null,
+    }
+}
@@ -0,0 +1,309 @@
+//! Backend that spawns mpv as a separate process, and talks to it through its IPC socket
+
+use {
+    super::{
+        ActivePtyInput, Backend, DemuxerChild, Output, PtyChildState, drain_pty_output,
+        reap_in_background, send_sigterm, spawn_pty_child,
+    },
+    crate::{
+        app::ModalPopup,
+        ipc::{self, SocketTransport},
+        logln,
+        util::str_ext::trim_lines,
+    },
+    anyhow::Context as _,
+    pty_process::blocking::Command as PtyCommand,
+    std::{
+        ffi::OsString,
+        io::{Read as _, Write as _},
+        ops::ControlFlow,
+        sync::{
+            Arc,
+            mpsc::{Receiver, TryRecvError},
+        },
+        time::Duration,
+    },
+};
+
+struct Running {
+    child: PtyChildState,
+    demux: Option<PtyChildState>,
+    ipc_bridge: ipc::Bridge,
+    socket_path: String,
+}
+
+/// mpv has been spawned, but we haven't managed to connect to its IPC socket yet.
+///
+/// Connecting happens on a background thread, so starting a song doesn't block the ui.
+struct StartingMpv {
+    child: PtyChildState,
+    demux: Option<PtyChildState>,
+    socket_path: String,
+    connect_recv: Receiver<anyhow::Result<SocketTransport>>,
+}
+
+/// How many times we try to connect to the IPC socket of a freshly spawned mpv
+const CONNECT_ATTEMPTS: u32 = 50;
+const CONNECT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+pub struct ProcessBackend {
+    running: Option<Running>,
+    starting: Option<StartingMpv>,
+    /// Used to give each spawned mpv a unique IPC socket path
+    spawn_counter: u32,
+}
+
+impl Backend for ProcessBackend {
+    fn play(
+        &mut self,
+        mpv_cmd: &str,
+        args: Vec<OsString>,
+        demux: Option<DemuxerChild>,
+    ) -> anyhow::Result<()> {
+        let (pty, pts) = pty_process::blocking::open()?;
+        let pty = Arc::new(pty);
+        // Each mpv instance gets its own socket, so we can't accidentally connect to
+        // a previous instance that is still in the process of shutting down.
+        self.spawn_counter += 1;
+        let socket_path = format!(
+            "/tmp/mpvfrog-{}-{}.sock",
+            std::process::id(),
+            self.spawn_counter
+        );
+        let ipc_arg = format!("--input-ipc-server={socket_path}");
+        let mut mpv_command = PtyCommand::new(mpv_cmd).args(args).args([&ipc_arg]);
+        let demux = match demux {
+            Some(demux) => {
+                mpv_command = mpv_command.stdin(demux.stdout);
+                Some(demux.state)
+            }
+            None => None,
+        };
+        let child = match mpv_command.spawn(pts) {
+            Ok(child) => child,
+            Err(e) => {
+                if let Some(demux) = demux {
+                    reap_in_background(None, Some(demux), None);
+                }
+                return Err(e.into());
+            }
+        };
+        let (connect_send, connect_recv) = std::sync::mpsc::channel();
+        let thread_socket_path = socket_path.clone();
+        std::thread::spawn(move || {
+            for i in 0..CONNECT_ATTEMPTS {
+                std::thread::sleep(CONNECT_INTERVAL);
+                match SocketTransport::connect(&thread_socket_path) {
+                    Ok(transport) => {
+                        // If the receiver is gone, the user has already moved on
+                        let _ = connect_send.send(Ok(transport));
+                        return;
+                    }
+                    Err(e) => logln!("mpv connection attempt #{i}: {e}"),
+                }
+            }
+            let _ = connect_send.send(Err(anyhow::anyhow!("Failed connect to mpv")));
+        });
+        self.starting = Some(StartingMpv {
+            child: spawn_pty_child(pty, child),
+            demux,
+            socket_path,
+            connect_recv,
+        });
+        Ok(())
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        if let Some(starting) = self.starting.take() {
+            // We're not connected yet, so we can't ask nicely over IPC
+            result = send_sigterm(&starting.child.child).context("Failed to terminate mpv");
+            reap_in_background(
+                Some(starting.child),
+                starting.demux,
+                Some(starting.socket_path),
+            );
+        }
+        if let Some(mut running) = self.running.take() {
+            if let Err(e) = running.ipc_bridge.quit() {
+                logln!("Failed to send quit command to mpv: {e}");
+                result = send_sigterm(&running.child.child).context("Failed to terminate mpv");
+            }
+            reap_in_background(
+                Some(running.child),
+                running.demux,
+                Some(running.socket_path),
+            );
+        }
+        result
+    }
+    fn update(&mut self, output: &mut Output, modal: &mut ModalPopup) -> ControlFlow<()> {
+        if self.starting.is_some() {
+            return self.update_starting(output, modal);
+        }
+        let Some(running) = &mut self.running else {
+            return ControlFlow::Break(());
+        };
+        if let Err(e) = running.ipc_bridge.handle_responses() {
+            modal.warn("Mpv IPC error", e);
+        }
+        match running.child.child.try_wait() {
+            Ok(None) => {}
+            Ok(Some(status)) => {
+                if !status.success() {
+                    let mut remaining_data = Vec::new();
+                    let result = (&*running.child.pty).read_to_end(&mut remaining_data);
+                    if let Err(e) = result {
+                        logln!("Failed to read mpv pty: {e}");
+                    }
+                    output.mpv_term.feed(&remaining_data);
+                    let stderr = trim_lines(output.mpv_term.contents_to_string());
+                    modal.error(
+                        "Abnormal mpv termination",
+                        format!("Mpv exited with status {status}\nStderr:\n{stderr}"),
+                    );
+                    if let Some(running) = self.running.take() {
+                        reap_in_background(None, running.demux, Some(running.socket_path));
+                    }
+                    return ControlFlow::Break(());
+                }
+            }
+            Err(e) => {
+                modal.error(
+                    "Abnormal mpv termination",
+                    format!("Error waiting on mpv: {e}"),
+                );
+                if let Some(running) = self.running.take() {
+                    reap_in_background(
+                        Some(running.child),
+                        running.demux,
+                        Some(running.socket_path),
+                    );
+                }
+                return ControlFlow::Break(());
+            }
+        }
+        if !drain_pty_output(&running.child.recv, &mut output.mpv_term) {
+            logln!("Mpv channel disconnected!");
+            if let Some(running) = self.running.take() {
+                reap_in_background(
+                    Some(running.child),
+                    running.demux,
+                    Some(running.socket_path),
+                );
+            }
+            return ControlFlow::Continue(());
+        }
+        if let Some(demux) = &running.demux
+            && !drain_pty_output(&demux.recv, &mut output.demux_term)
+        {
+            logln!("Demux channel disconnected!");
+        }
+        ControlFlow::Continue(())
+    }
+    fn active(&self) -> bool {
+        self.running.is_some() || self.starting.is_some()
+    }
+    fn starting(&self) -> bool {
+        self.starting.is_some()
+    }
+    fn bridge(&self) -> Option<&ipc::Bridge> {
+        self.running.as_ref().map(|running| &running.ipc_bridge)
+    }
+    fn bridge_mut(&mut self) -> Option<&mut ipc::Bridge> {
+        self.running.as_mut().map(|running| &mut running.ipc_bridge)
+    }
+    fn demuxer_active(&self) -> bool {
+        self.running
+            .as_ref()
+            .is_some_and(|running| running.demux.is_some())
+    }
+    fn send_input(&mut self, target: &ActivePtyInput, s: &str) {
+        let Some(running) = &mut self.running else {
+            return;
+        };
+        let mut pty = match target {
+            ActivePtyInput::Mpv => &*running.child.pty,
+            ActivePtyInput::Demuxer => match &running.demux {
+                Some(demux) => &demux.pty,
+                None => {
+                    logln!("Trying to write to demux while no demux");
+                    return;
+                }
+            },
+        };
+        if let Err(e) = pty.write_all(s.as_bytes()) {
+            logln!("Failed to write to pty: {e}");
+        }
+    }
+}
+
+impl ProcessBackend {
+    /// Check whether we have managed to connect to the mpv we're starting
+    fn update_starting(&mut self, output: &mut Output, modal: &mut ModalPopup) -> ControlFlow<()> {
+        let Some(starting) = &mut self.starting else {
+            return ControlFlow::Continue(());
+        };
+        drain_pty_output(&starting.child.recv, &mut output.mpv_term);
+        if let Some(demux) = &starting.demux {
+            drain_pty_output(&demux.recv, &mut output.demux_term);
+        }
+        let exit_status = match starting.child.child.try_wait() {
+            Ok(status) => status,
+            Err(e) => {
+                logln!("Error waiting on starting mpv: {e}");
+                None
+            }
+        };
+        if let Some(status) = exit_status {
+            // Give the pty reader a chance to hand over the last bits of output
+            drain_pty_output(&starting.child.recv, &mut output.mpv_term);
+            if let Some(starting) = self.starting.take() {
+                reap_in_background(None, starting.demux, Some(starting.socket_path));
+            }
+            // Short songs can be over before we get to connect
+            if status.success() {
+                return ControlFlow::Continue(());
+            }
+            let stderr = trim_lines(output.mpv_term.contents_to_string());
+            modal.error(
+                "Play error",
+                format!("mpv exited with {status}.\nStderr:\n{stderr}"),
+            );
+            return ControlFlow::Break(());
+        }
+        let result = match starting.connect_recv.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return ControlFlow::Continue(()),
+            Err(TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("mpv connection thread disappeared"))
+            }
+        };
+        let Some(starting) = self.starting.take() else {
+            return ControlFlow::Continue(());
+        };
+        match result.and_then(|transport| ipc::Bridge::new(Box::new(transport))) {
+            Ok(ipc_bridge) => {
+                self.running = Some(Running {
+                    child: starting.child,
+                    demux: starting.demux,
+                    ipc_bridge,
+                    socket_path: starting.socket_path,
+                });
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                modal.error("Play error", format!("{e:?}"));
+                if let Err(e) = send_sigterm(&starting.child.child) {
+                    logln!("Failed to terminate mpv: {e}");
+                }
+                reap_in_background(
+                    Some(starting.child),
+                    starting.demux,
+                    Some(starting.socket_path),
+                );
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
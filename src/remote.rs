@@ -0,0 +1,135 @@
+//! Controlling the running instance from the command line
+//!
+//! Commands are sent as JSON over the single instance channel, and answered with a [`Reply`].
+
+use {
+    serde::{Deserialize, Serialize},
+    std::{path::PathBuf, str::FromStr},
+};
+
+#[derive(clap::Subcommand, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "command")]
+pub enum RemoteCmd {
+    /// Start playing, or resume if paused
+    Play,
+    Pause,
+    /// Play or pause
+    Toggle,
+    Next,
+    Prev,
+    Stop,
+    /// Seek to a position in seconds, or relative to the current one with a + or - sign
+    Seek {
+        #[arg(allow_hyphen_values = true)]
+        target: SeekTarget,
+    },
+    Volume {
+        volume: u8,
+    },
+    Speed {
+        speed: f64,
+    },
+    /// Play a song from the music folder after the current one.
+    /// Plays it right away if nothing is playing.
+    Enqueue {
+        path: PathBuf,
+    },
+    /// Open a folder, or play a song
+    Open {
+        path: PathBuf,
+    },
+    /// Print the playback status as JSON
+    Status,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SeekTarget {
+    Absolute(f64),
+    Relative(f64),
+}
+
+impl SeekTarget {
+    pub fn resolve(self, current: f64) -> f64 {
+        match self {
+            Self::Absolute(pos) => pos,
+            Self::Relative(offset) => (current + offset).max(0.0),
+        }
+    }
+}
+
+impl FromStr for SeekTarget {
+    type Err = std::num::ParseFloatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(['+', '-']) {
+            s.parse().map(Self::Relative)
+        } else {
+            s.parse().map(Self::Absolute)
+        }
+    }
+}
+
+/// What the running instance replies to a command with: what it returned, or the error message
+pub type Reply = Result<Option<serde_json::Value>, String>;
+
+impl RemoteCmd {
+    /// Make relative paths absolute, since the running instance can have another working directory
+    pub fn canonicalize_paths(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Enqueue { path } | Self::Open { path } => *path = path.canonicalize()?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// What `status` replies with
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Status {
+    pub state: PlayState,
+    /// Path of the song
+    pub path: Option<PathBuf>,
+    /// Position in seconds
+    pub pos: f64,
+    /// Duration in seconds
+    pub duration: f64,
+    pub volume: u8,
+    pub speed: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayState {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+}
+
+#[test]
+fn test_remote_cmd() {
+    use clap::Parser;
+    #[derive(clap::Parser)]
+    struct Cli {
+        #[command(subcommand)]
+        cmd: RemoteCmd,
+    }
+    let parse = |args: &[&str]| {
+        Cli::try_parse_from(std::iter::once("mpvfrog").chain(args.iter().copied()))
+            .unwrap()
+            .cmd
+    };
+    let seek = |target| RemoteCmd::Seek { target };
+    assert_eq!(parse(&["seek", "+10"]), seek(SeekTarget::Relative(10.0)));
+    assert_eq!(parse(&["seek", "-5.5"]), seek(SeekTarget::Relative(-5.5)));
+    assert_eq!(parse(&["seek", "90"]), seek(SeekTarget::Absolute(90.0)));
+    assert_eq!(parse(&["volume", "60"]), RemoteCmd::Volume { volume: 60 });
+    assert_eq!(SeekTarget::Relative(-10.0).resolve(4.0), 0.0);
+    let json = serde_json::to_string(&parse(&["speed", "1.2"])).unwrap();
+    assert_eq!(json, r#"{"command":"speed","speed":1.2}"#);
+    assert_eq!(
+        serde_json::from_str::<RemoteCmd>(r#"{"command":"toggle"}"#).unwrap(),
+        RemoteCmd::Toggle
+    );
+}
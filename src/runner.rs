@@ -3,7 +3,10 @@
 use {
     crate::{
         app::{self, App, tray::EventFlags},
+        control_socket::ControlServer,
+        logln,
         rect_math::{Rect, Vec2, rect_ensure_within},
+        remote::{RemoteCmd, Reply},
         util::result_ext::ResultModalExt as _,
     },
     egui_sf2g::{
@@ -15,7 +18,7 @@ use {
             window::{Event, Key, Scancode, Style, VideoMode},
         },
     },
-    std::{path::PathBuf, time::Duration},
+    std::time::Duration,
     x11rb::protocol::xproto::{AtomEnum, ConnectionExt, PropMode},
 };
 
@@ -47,6 +50,17 @@ pub fn run(
             eprintln!("Failed to load fallback font {path:?}: {e}")
         }
     }
+    let mut control_server = if app.core.cfg.control_socket {
+        match ControlServer::bind() {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("Failed to open control socket: {e:#}");
+                None
+            }
+        }
+    } else {
+        None
+    };
     let mut win_vis_state = WindowVisState::Visible;
     'mainloop: loop {
         let mut event_flags;
@@ -61,16 +75,21 @@ pub fn run(
             && let Some(msg) = stream.recv()
         {
             match msg {
-                existing_instance::Msg::String(path) => {
-                    let path: PathBuf = path.into();
-                    if path.is_dir() {
-                        app::open_folder(&mut app.core, path);
-                    } else if path.is_file()
-                        && let Some(parent) = path.parent()
-                    {
-                        app::open_folder(&mut app.core, parent.to_owned());
-                        let stripped = path.strip_prefix(parent).unwrap();
-                        app.queue_to_play(stripped.to_path_buf());
+                existing_instance::Msg::String(msg) => {
+                    match serde_json::from_str::<RemoteCmd>(&msg) {
+                        Ok(cmd) => {
+                            let reply: Reply = app.exec_remote(cmd).map_err(|e| format!("{e:#}"));
+                            stream.send(existing_instance::Msg::String(
+                                serde_json::to_string(&reply).unwrap(),
+                            ));
+                        }
+                        // Just a path to open, from `mpvfrog <path>` or an older version.
+                        // Nothing waits for a reply to these.
+                        Err(_) => {
+                            if let Err(e) = app.exec_remote(RemoteCmd::Open { path: msg.into() }) {
+                                logln!("Failed to open path: {e:#}");
+                            }
+                        }
                     }
                 }
                 existing_instance::Msg::Nudge => {
@@ -79,9 +98,27 @@ pub fn run(
                 _ => {}
             }
         }
+        if let Some(server) = &mut control_server {
+            server.update(&mut app);
+        }
         if event_flags.quit_clicked {
             break;
         }
+        if event_flags.toggle_pause {
+            app.core.play_or_toggle_pause(&mut app.modal);
+        }
+        if event_flags.volume_change != 0 {
+            app.core
+                .change_volume(event_flags.volume_change)
+                .err_popup("Volume change error", &mut app.modal);
+        }
+        if event_flags.seek_by != 0.0
+            && let Some(info) = app.core.mpv_handler.time_info()
+        {
+            app.core
+                .seek((info.pos + event_flags.seek_by).max(0.0))
+                .err_popup("Seek error", &mut app.modal);
+        }
         if event_flags.activated {
             // First, close the tray popup if it's open
             if tray_popup_win.is_some() {
@@ -100,9 +137,16 @@ pub fn run(
             if tray_popup_win.is_some() {
                 tray_popup_win = None;
             } else {
+                let mut height = 146;
+                if app.core.cfg.level_meter && app.core.cfg.level_meter_in_tray {
+                    height += 16;
+                }
+                if app.core.cfg.cover_art && app.core.cover_art.is_some() {
+                    height += TRAY_COVER_SIZE as i32 - 14;
+                }
                 let desired = Rect {
                     pos: Vec2 { x, y },
-                    size: Vec2 { x: 200, y: 100 },
+                    size: Vec2 { x: 200, y: height },
                 };
                 let desk_size = VideoMode::desktop_mode();
                 let desk_rect = Rect {
@@ -276,6 +320,9 @@ fn toggle_win_visible(
     }
 }
 
+/// Size of the cover art in the tray popup
+const TRAY_COVER_SIZE: f32 = 48.0;
+
 enum TrayUpdateMsg {
     QuitApp,
     CloseTray,
@@ -337,14 +384,28 @@ fn update_tray_window(win: &mut CtxMenuWin, app: &mut App) -> Option<TrayUpdateM
                             .err_popup("Volume set error", &mut app.modal);
                     }
                 });
+                ui.horizontal(|ui| {
+                    ui.label("🎧");
+                    app::ui::audio_device_combo(ui, &mut app.core, &mut app.modal, 160.0);
+                });
+                if app.core.cfg.level_meter && app.core.cfg.level_meter_in_tray {
+                    app::ui::level_meter(ui, &mut app.core, 184.0);
+                }
                 let play_pause_label = if app.paused_or_stopped() {
                     "▶"
                 } else {
                     "⏸"
                 };
-                if let Some(name) = app.currently_playing_name() {
-                    ui.add(egui::Label::new(name).wrap_mode(egui::TextWrapMode::Extend));
-                }
+                ui.horizontal(|ui| {
+                    if app.core.cfg.cover_art
+                        && let Some(art) = &app.core.cover_art
+                    {
+                        app::ui::cover_art_image(ui, art, TRAY_COVER_SIZE);
+                    }
+                    if let Some(name) = app.currently_playing_name() {
+                        ui.add(egui::Label::new(name).wrap_mode(egui::TextWrapMode::Extend));
+                    }
+                });
                 ui.add_space(4.0);
                 ui.horizontal(|ui| {
                     ui.add_space(38.0);
@@ -355,12 +416,17 @@ fn update_tray_window(win: &mut CtxMenuWin, app: &mut App) -> Option<TrayUpdateM
                         app.core.play_or_toggle_pause(&mut app.modal);
                     }
                     if ui.button("⏹").clicked() {
-                        app.core.stop_music();
+                        app.core
+                            .stop_music()
+                            .err_popup("Stop error", &mut app.modal);
                     }
                     if ui.button(app::ui::ICO_NEXT).clicked() {
                         app.core.play_next(&mut app.modal);
                     }
                 });
+                ui.horizontal(|ui| {
+                    app::ui::sleep_timer_row(ui, &mut app.core, &mut app.modal);
+                });
             });
             if quit {
                 msg = Some(TrayUpdateMsg::QuitApp);
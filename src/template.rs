@@ -0,0 +1,45 @@
+//! Format templates like `{artist} - {title} [{pos}/{dur}]`, for status text
+
+/// Fill in the `{name}` placeholders of `template` with what `var` returns for them.
+///
+/// Unknown placeholders are left alone. `{{` and `}}` stand for literal braces.
+pub fn render<S: AsRef<str>>(template: &str, mut var: impl FnMut(&str) -> Option<S>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(idx) = rest.find(['{', '}']) {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        if let Some(after) = rest.strip_prefix("{{") {
+            out.push('{');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("}}") {
+            out.push('}');
+            rest = after;
+        } else if rest.starts_with('{')
+            && let Some(end) = rest.find('}')
+            && let Some(value) = var(&rest[1..end])
+        {
+            out.push_str(value.as_ref());
+            rest = &rest[end + 1..];
+        } else {
+            out.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[test]
+fn test_render() {
+    let var = |name: &str| match name {
+        "artist" => Some("The Band"),
+        "title" => Some("Song"),
+        "empty" => Some(""),
+        _ => None,
+    };
+    assert_eq!(render("{artist} - {title}", var), "The Band - Song");
+    assert_eq!(render("[{empty}] {nope} {{x}}", var), "[] {nope} {x}");
+    assert_eq!(render("unclosed {title", var), "unclosed {title");
+    assert_eq!(render("}{title}{", var), "}Song{");
+}
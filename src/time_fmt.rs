@@ -19,10 +19,27 @@ impl fmt::Display for FfmpegTimeFmt {
     }
 }
 
+/// Like `3:07`, or `1:02:03` for an hour or more. For status text.
+pub struct ShortTimeFmt(pub f64);
+
+impl fmt::Display for ShortTimeFmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.max(0.0) as u64;
+        let (hh, mm, ss) = (secs / 3600, secs / 60 % 60, secs % 60);
+        if hh > 0 {
+            write!(f, "{hh}:{mm:02}:{ss:02}")
+        } else {
+            write!(f, "{mm}:{ss:02}")
+        }
+    }
+}
+
 #[test]
 fn test_time_fmt() {
     assert_eq!(&FfmpegTimeFmt(0.0).to_string()[..], "00:00:00.000");
     assert_eq!(&FfmpegTimeFmt(24.56).to_string()[..], "00:00:24.560");
     assert_eq!(&FfmpegTimeFmt(119.885).to_string()[..], "00:01:59.885");
     assert_eq!(&FfmpegTimeFmt(52349.345).to_string()[..], "14:32:29.345");
+    assert_eq!(&ShortTimeFmt(187.9).to_string()[..], "3:07");
+    assert_eq!(&ShortTimeFmt(3723.0).to_string()[..], "1:02:03");
 }
@@ -0,0 +1,25 @@
+use {
+    anyhow::Context as _,
+    directories::ProjectDirs,
+    std::{
+        hash::{DefaultHasher, Hash as _, Hasher as _},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Where something derived from the song at `path` is cached, in the `dir` cache directory.
+///
+/// The modification time and size are part of the key, so changed files get a new entry.
+pub fn song_cache_path(path: &Path, dir: &str) -> anyhow::Result<PathBuf> {
+    let meta = std::fs::metadata(path)?;
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    meta.modified()?.hash(&mut hasher);
+    meta.len().hash(&mut hasher);
+    let proj_dirs =
+        ProjectDirs::from("", "crumblingstatue", "mpvfrog").context("No home directory")?;
+    Ok(proj_dirs
+        .cache_dir()
+        .join(dir)
+        .join(format!("{:016x}", hasher.finish())))
+}
@@ -1,20 +1,3 @@
-pub trait StrExt {
-    fn find_after(&self, pattern: &str) -> Option<usize>;
-    fn find_token_after(&self, pattern: &str) -> Option<std::ops::Range<usize>>;
-}
-
-impl StrExt for str {
-    fn find_after(&self, pattern: &str) -> Option<usize> {
-        self.find(pattern).map(|pos| pos + pattern.len())
-    }
-
-    fn find_token_after(&self, pattern: &str) -> Option<std::ops::Range<usize>> {
-        let pos = self.find_after(pattern)?;
-        let ws = self[pos..].find(|c: char| c.is_whitespace())?;
-        Some(pos..pos + ws)
-    }
-}
-
 // Replace trailing whitespace of each line with single line terminator
 //
 // This prevents the text viewer wrapping long lines
@@ -26,3 +9,71 @@ pub fn trim_lines(s: String) -> String {
     }
     out
 }
+
+/// Split a command line into arguments, like a shell would.
+///
+/// Quotes (`"` or `'`) keep whitespace in an argument, and `\` escapes the next character,
+/// except in single quotes.
+pub fn split_args(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                // Even `''` is an argument
+                arg.get_or_insert_default();
+            }
+            (Some('\''), c) => arg.get_or_insert_default().push(c),
+            (_, '\\') => {
+                if let Some(c) = chars.next() {
+                    arg.get_or_insert_default().push(c);
+                }
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (_, c) => arg.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+/// Join arguments into a command line that [`split_args`] splits back into them
+pub fn join_args(args: &[String]) -> String {
+    let quoted: Vec<String> = (args.iter())
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && !arg.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+#[test]
+fn test_split_args() {
+    assert_eq!(
+        split_args("  --no-video\t--config-dir='/home/me/My Config' \"a b\"c \\\"d ''"),
+        [
+            "--no-video",
+            "--config-dir=/home/me/My Config",
+            "a bc",
+            "\"d",
+            ""
+        ]
+    );
+    assert!(split_args(" ").is_empty());
+    let args = ["--title=it's", "a\\b", "", "--af=lavfi=[volume=2]"].map(String::from);
+    assert_eq!(
+        join_args(&args),
+        r"'--title=it'\''s' 'a\b' '' --af=lavfi=[volume=2]"
+    );
+    assert_eq!(split_args(&join_args(&args)), args);
+}
@@ -0,0 +1,187 @@
+//! Peak envelopes of songs, for drawing the seek bar as a waveform
+
+use {
+    crate::util::cache::song_cache_path,
+    anyhow::Context as _,
+    std::{
+        io::Read,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            mpsc::Receiver,
+        },
+    },
+};
+
+/// Length of the audio each peak covers, in seconds
+pub const BUCKET_SECS: f64 = 0.1;
+/// Sample rate we have ffmpeg decode to. Plenty for finding peaks.
+const SAMPLE_RATE: usize = 8000;
+const SAMPLES_PER_BUCKET: usize = (SAMPLE_RATE as f64 * BUCKET_SECS) as usize;
+
+/// Incremented by every [`load`]. Only the latest waveform is wanted, so older decodes give up.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Peak envelope of a song
+pub struct Waveform {
+    /// Peak of each [`BUCKET_SECS`] long piece of the song, in the 0..=255 range
+    pub peaks: Vec<u8>,
+}
+
+impl Waveform {
+    /// Highest peak between the two points in time (in seconds)
+    pub fn peak_between(&self, from: f64, to: f64) -> u8 {
+        let bucket = |time: f64| ((time / BUCKET_SECS) as usize).min(self.peaks.len());
+        let (from, to) = (bucket(from), bucket(to));
+        // Make sure we look at at least one bucket, even when zoomed in
+        let to = to.max(from + 1).min(self.peaks.len());
+        self.peaks
+            .get(from..to)
+            .and_then(|peaks| peaks.iter().max().copied())
+            .unwrap_or(0)
+    }
+}
+
+/// Compute the peak envelope of raw signed 16 bit mono PCM
+pub fn peaks_from_pcm(mut pcm: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut peaks = Vec::new();
+    let mut buf = vec![0; SAMPLES_PER_BUCKET * 2];
+    loop {
+        let len = read_fill(&mut pcm, &mut buf)?;
+        if len < 2 {
+            break;
+        }
+        let peak = buf[..len]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        peaks.push((peak >> 7).min(255) as u8);
+    }
+    Ok(peaks)
+}
+
+/// Read until `buf` is full or the reader runs out. Returns the number of bytes read.
+fn read_fill(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+/// Load the waveform of `path` in the background, from the cache if possible.
+///
+/// Loading the waveform of another song cancels this one.
+pub fn load(path: PathBuf) -> Receiver<anyhow::Result<Waveform>> {
+    let (send, recv) = std::sync::mpsc::channel();
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    std::thread::spawn(move || {
+        // The receiver might have been dropped in the meantime, if the song was changed
+        let _ = send.send(load_blocking(&path, generation));
+    });
+    recv
+}
+
+fn load_blocking(path: &Path, generation: u64) -> anyhow::Result<Waveform> {
+    let cache_path = song_cache_path(path, "waveforms")?;
+    if let Ok(peaks) = std::fs::read(&cache_path) {
+        return Ok(Waveform { peaks });
+    }
+    let peaks = decode_peaks(path, generation)?;
+    if let Some(dir) = cache_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Write to a temporary file, so a crash midway doesn't leave a truncated waveform behind
+    let tmp_path = cache_path.with_extension("tmp");
+    std::fs::write(&tmp_path, &peaks)?;
+    std::fs::rename(tmp_path, &cache_path)?;
+    Ok(Waveform { peaks })
+}
+
+/// Reads the output of ffmpeg, until a newer waveform is requested
+struct Superseded<R> {
+    inner: R,
+    generation: u64,
+}
+
+impl<R: Read> Read for Superseded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if GENERATION.load(Ordering::Relaxed) != self.generation {
+            return Err(std::io::Error::other(
+                "Another song's waveform was requested",
+            ));
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// Decode the song with ffmpeg, and compute its peaks.
+///
+/// Gives up if [`GENERATION`] moves past `generation`.
+fn decode_peaks(path: &Path, generation: u64) -> anyhow::Result<Vec<u8>> {
+    let mut child = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(path)
+        .args(["-vn", "-ac", "1", "-ar", &SAMPLE_RATE.to_string()])
+        .args(["-f", "s16le", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run ffmpeg")?;
+    let stdout = child.stdout.take().context("ffmpeg has no stdout")?;
+    let peaks = match peaks_from_pcm(Superseded {
+        inner: stdout,
+        generation,
+    }) {
+        Ok(peaks) => peaks,
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e.into());
+        }
+    };
+    let status = child.wait()?;
+    anyhow::ensure!(status.success(), "ffmpeg failed ({status})");
+    Ok(peaks)
+}
+
+#[test]
+fn test_peaks() {
+    let mut pcm = Vec::new();
+    // One loud bucket, then a quiet, partial one
+    for i in 0..SAMPLES_PER_BUCKET {
+        let sample: i16 = if i == 10 { -32768 } else { 100 };
+        pcm.extend(sample.to_le_bytes());
+    }
+    for _ in 0..10 {
+        pcm.extend(1280i16.to_le_bytes());
+    }
+    let waveform = Waveform {
+        peaks: peaks_from_pcm(&pcm[..]).unwrap(),
+    };
+    assert_eq!(waveform.peaks, [255, 10]);
+    assert_eq!(waveform.peak_between(0.0, 0.05), 255);
+    assert_eq!(waveform.peak_between(0.15, 0.2), 10);
+    assert_eq!(waveform.peak_between(0.0, 10.0), 255);
+    assert_eq!(waveform.peak_between(5.0, 10.0), 0);
+}
+
+#[test]
+fn test_superseded() {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut reader = Superseded {
+        inner: &[0_u8; 4][..],
+        generation,
+    };
+    let mut buf = [0; 2];
+    assert_eq!(reader.read(&mut buf).unwrap(), 2);
+    // Another song came along
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    assert!(reader.read(&mut buf).is_err());
+}